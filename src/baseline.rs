@@ -0,0 +1,141 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::diagnostic::Diagnostic;
+use crate::error::{read_file, write_file, Error};
+use crate::{current_dir, relative_file_name};
+use serde_json::{json, Value};
+use std::path::Path;
+
+// A baseline entry identifies a finding by its fingerprint (the file, the kind of the finding, and
+// the text of the line), so it still matches after lines are added or removed elsewhere in the
+// file. The line number and message are only recorded to make the file readable.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct BaselineEntry {
+    pub file_name: String,
+    pub kind: String,
+    pub fingerprint: String,
+    pub line_number: Option<usize>,
+    pub message: String,
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Baseline {
+    pub entries: Vec<BaselineEntry>,
+}
+
+impl Baseline {
+    #[doc(hidden)]
+    pub fn read(path: &str) -> Result<Self, Error> {
+        let text = read_file(Path::new(path))?;
+        let invalid_file = |reason: String| Error::InvalidFile {
+            file_name: path.to_string(),
+            reason,
+        };
+        let baseline: Value =
+            serde_json::from_str(&text).map_err(|error| invalid_file(error.to_string()))?;
+        let entries = baseline["findings"]
+            .as_array()
+            .ok_or_else(|| invalid_file("missing findings array".to_string()))?
+            .iter()
+            .map(|finding| {
+                Ok(BaselineEntry {
+                    file_name: string_field(finding, "file")
+                        .ok_or_else(|| invalid_file("finding without a file".to_string()))?,
+                    kind: string_field(finding, "kind")
+                        .ok_or_else(|| invalid_file("finding without a kind".to_string()))?,
+                    fingerprint: string_field(finding, "fingerprint")
+                        .ok_or_else(|| invalid_file("finding without a fingerprint".to_string()))?,
+                    line_number: finding["line"]
+                        .as_u64()
+                        .and_then(|line_number| usize::try_from(line_number).ok()),
+                    message: finding["message"].as_str().unwrap_or_default().to_string(),
+                })
+            })
+            .collect::<Result<Vec<BaselineEntry>, Error>>()?;
+        Ok(Self { entries })
+    }
+
+    // Each entry matches at most one finding, so if a finding is repeated on an identical line, the
+    // new copies are still reported. The matched entries are removed, so the remaining ones are
+    // those which were fixed.
+    #[doc(hidden)]
+    pub fn filter(&mut self, diagnostics: &mut Vec<Diagnostic>) {
+        diagnostics.retain(|diagnostic| {
+            let fingerprint = diagnostic.fingerprint();
+            if let Some(entry_index) = self
+                .entries
+                .iter()
+                .position(|entry| entry.fingerprint == fingerprint)
+            {
+                self.entries.remove(entry_index);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    // These are reported so the baseline can be regenerated to keep it from hiding new findings.
+    // Entries whose (canonical) file name and line are not in the checked scope are skipped, since
+    // their findings were not looked for.
+    #[doc(hidden)]
+    pub fn report_fixed_entries(&self, is_in_scope: impl Fn(&str, Option<usize>) -> bool) {
+        for entry in &self.entries {
+            let file_name = current_dir().join(&entry.file_name);
+            if !is_in_scope(&file_name.to_string_lossy(), entry.line_number) {
+                continue;
+            }
+            eprintln!(
+                "{}{}: fixed baseline {} finding: {}",
+                entry.file_name,
+                entry
+                    .line_number
+                    .map_or_else(String::new, |line_number| format!(":{line_number}")),
+                entry.kind,
+                entry.message
+            );
+        }
+    }
+}
+
+#[doc(hidden)]
+fn string_field(finding: &Value, name: &str) -> Option<String> {
+    finding[name].as_str().map(str::to_string)
+}
+
+#[doc(hidden)]
+pub fn write_baseline(path: &str, diagnostics: &[Diagnostic]) -> Result<(), Error> {
+    let findings: Vec<Value> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            json!({
+                "file": relative_file_name(&diagnostic.file_name),
+                "kind": diagnostic.kind.id(),
+                "fingerprint": diagnostic.fingerprint(),
+                "line": diagnostic.line_number,
+                "message": diagnostic.message,
+            })
+        })
+        .collect();
+    let baseline = json!({ "version": 1, "findings": findings });
+    write_file(
+        path,
+        &(serde_json::to_string_pretty(&baseline).unwrap() + "\n"),
+    )
+}
@@ -0,0 +1,157 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::error::Error;
+use crate::source_root_name;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Package {
+    pub name: String,
+    pub source_roots: Vec<String>,
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Workspace {
+    pub members: Vec<Package>,
+    pub default_members: Vec<String>,
+}
+
+// The default members are the ones `cargo` itself would use when invoked in the current working
+// directory: the package whose manifest is there, or the workspace default members if it is a
+// virtual manifest. Unexpected metadata is reported against the manifest, and the packages (or
+// targets) it describes are skipped.
+#[doc(hidden)]
+pub fn cargo_workspace(errors: &mut Vec<Error>) -> Option<Workspace> {
+    let manifest_path = fs::canonicalize("Cargo.toml").ok()?;
+    let metadata = cargo_metadata()?;
+    let mut invalid_metadata = |reason: &str| {
+        errors.push(Error::InvalidFile {
+            file_name: manifest_path.to_string_lossy().to_string(),
+            reason: format!("invalid cargo metadata: {reason}"),
+        });
+    };
+    let Some(packages) = metadata["packages"].as_array() else {
+        invalid_metadata("missing packages array");
+        return None;
+    };
+    let mut members = Vec::new();
+    for package in packages {
+        match workspace_member(package) {
+            Ok(member) => members.push(member),
+            Err(reason) => invalid_metadata(reason),
+        }
+    }
+    let current_package = packages
+        .iter()
+        .find(|package| package["manifest_path"].as_str() == manifest_path.to_str());
+    let default_members = if let Some(package) = current_package {
+        package["name"]
+            .as_str()
+            .map(str::to_string)
+            .into_iter()
+            .collect()
+    } else {
+        let Some(default_ids) = metadata["workspace_default_members"]
+            .as_array()
+            .or_else(|| metadata["workspace_members"].as_array())
+        else {
+            invalid_metadata("missing workspace_members array");
+            return None;
+        };
+        packages
+            .iter()
+            .filter(|package| default_ids.contains(&package["id"]))
+            .filter_map(|package| package["name"].as_str().map(str::to_string))
+            .collect()
+    };
+    Some(Workspace {
+        members,
+        default_members,
+    })
+}
+
+// The error is the reason the package can't be used.
+#[doc(hidden)]
+fn workspace_member(package: &Value) -> Result<Package, &'static str> {
+    let name = package["name"].as_str().ok_or("package without a name")?;
+    Ok(Package {
+        name: name.to_string(),
+        source_roots: targets_source_roots(package)?,
+    })
+}
+
+#[doc(hidden)]
+fn cargo_metadata() -> Option<Value> {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let output = Command::new(cargo)
+        .args(["metadata", "--no-deps", "--format-version", "1"])
+        .stderr(Stdio::inherit())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    serde_json::from_slice(&output.stdout).ok()
+}
+
+// Each target contributes the directory containing its main source file (e.g., `src` for
+// `src/lib.rs`, `tests` for `tests/foo.rs`), except for targets whose main source file is
+// directly in the package directory (e.g., `build.rs`), which contribute just that file. A root
+// nested in another root (e.g., `src/bin` for `src/bin/x.rs` when there is also `src/main.rs`) is
+// dropped, so its files are only scanned once.
+#[doc(hidden)]
+fn targets_source_roots(package: &Value) -> Result<Vec<String>, &'static str> {
+    let package_dir = package["manifest_path"]
+        .as_str()
+        .and_then(|manifest_path| Path::new(manifest_path).parent())
+        .ok_or("package without a manifest_path")?;
+    let targets = package["targets"]
+        .as_array()
+        .ok_or("package without a targets array")?;
+    let mut roots: Vec<String> = Vec::new();
+    for target in targets {
+        let src_path = Path::new(
+            target["src_path"]
+                .as_str()
+                .ok_or("target without a src_path")?,
+        );
+        let root = match src_path.parent() {
+            Some(src_dir) if src_dir != package_dir => src_dir,
+            _ => src_path,
+        };
+        if let Ok(canonical) = fs::canonicalize(root) {
+            let root = source_root_name(&canonical);
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+    }
+    Ok(roots
+        .iter()
+        .filter(|root| {
+            !roots.iter().any(|other_root| {
+                other_root != *root && Path::new(root).starts_with(Path::new(other_root))
+            })
+        })
+        .cloned()
+        .collect())
+}
@@ -0,0 +1,428 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::config::{Config, CONFIG_FILE_NAME, DEFAULT_FIX_REGION_LINES};
+use crate::error::write_file;
+use crate::report::{ColorChoice, OutputFormat};
+use crate::summary::SummaryFormat;
+use crate::{FlakyPolicy, ERROR_EXIT_STATUS, VERSION};
+use clap::builder::PossibleValuesParser;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use clap_complete::Shell;
+use std::ffi::OsString;
+use std::path::Path;
+
+// The commands which go through the checks. The other commands (`init` and `completions`) are
+// completed while processing the arguments.
+#[doc(hidden)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    Check,
+    Stats,
+    Explain(String, usize),
+}
+
+#[doc(hidden)]
+const AFTER_HELP: &str = "\
+Exit status: 0 if the coverage annotations are correct, 1 if there are wrong coverage annotations,
+and 2 for usage, configuration or input file errors (which prevent fully checking them).";
+
+#[doc(hidden)]
+pub fn cli_command() -> Command {
+    Command::new("cargo-coverage-annotations")
+        .bin_name("cargo coverage-annotations")
+        .version(VERSION)
+        .about("Ensure annotations in code match actual coverage.")
+        .after_help(AFTER_HELP)
+        .args(global_args())
+        .args(check_args())
+        .args(fix_args().map(|arg| arg.hide(true)))
+        .subcommand(
+            Command::new("check")
+                .about("Verify the coverage annotations match the coverage (the default)")
+                .args(check_args()),
+        )
+        .subcommand(
+            Command::new("fix")
+                .about("Fix wrong coverage annotations in the source files")
+                .args(fix_args())
+                .arg(format_arg()),
+        )
+        .subcommand(
+            Command::new("report")
+                .about("Print a report of the findings in a machine-readable format")
+                .arg(format_arg().required(true))
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .value_name("PATH")
+                        .help("Write the report to a file instead of the standard output"),
+                ),
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Print coverage annotation statistics for each file")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Print the statistics as JSON"),
+                ),
+        )
+        .subcommand(
+            Command::new("explain")
+                .about("Explain how the coverage annotation of a source line was derived")
+                .arg(
+                    Arg::new("location")
+                        .value_name("FILE:LINE")
+                        .required(true)
+                        .value_parser(parse_location)
+                        .help("The source line, e.g. src/lib.rs:123"),
+                ),
+        )
+        .subcommand(
+            Command::new("init").about(
+                "Create a coverage-annotations.toml file holding the effective configuration",
+            ),
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("Print a shell completion script")
+                .arg(
+                    Arg::new("shell")
+                        .value_name("SHELL")
+                        .required(true)
+                        .value_parser(value_parser!(Shell)),
+                ),
+        )
+}
+
+// These apply to all the commands, so may be given either before or after the command name. The
+// check and fix arguments are also accepted without a command name (which means `check`), but
+// not before a command name.
+#[doc(hidden)]
+fn global_args() -> [Arg; 9] {
+    [
+        Arg::new("flaky")
+            .long("flaky")
+            .value_name("POLICY")
+            .value_parser(PossibleValuesParser::new(
+                FlakyPolicy::ALL.map(FlakyPolicy::name),
+            ))
+            .help("How to treat FLAKY TESTED lines (default: maybe-tested)"),
+        Arg::new("coverage")
+            .long("coverage")
+            .value_name("PATH")
+            .action(ArgAction::Append)
+            .help("Coverage file(s) glob pattern (default: search the current directory)"),
+        Arg::new("source")
+            .long("source")
+            .value_name("PATH")
+            .action(ArgAction::Append)
+            .help("Source root directory or file (default: the package targets)"),
+        Arg::new("workspace")
+            .long("workspace")
+            .action(ArgAction::SetTrue)
+            .help("Check all the workspace members"),
+        Arg::new("package")
+            .long("package")
+            .short('p')
+            .value_name("NAME")
+            .action(ArgAction::Append)
+            .help("Check only the specified workspace member(s)"),
+        Arg::new("color")
+            .long("color")
+            .value_name("WHEN")
+            .value_parser(PossibleValuesParser::new(
+                ColorChoice::ALL.map(ColorChoice::name),
+            ))
+            .help("Whether to use colors in the human readable output (default: auto)"),
+        Arg::new("baseline")
+            .long("baseline")
+            .value_name("PATH")
+            .help("Only report findings which are not in the baseline file"),
+        Arg::new("changed-since")
+            .long("changed-since")
+            .value_name("REF")
+            .help("Only report wrong annotations of lines changed since the git reference"),
+        Arg::new("print-config")
+            .long("print-config")
+            .action(ArgAction::SetTrue)
+            .help("Print the effective configuration and exit"),
+    ]
+    .map(|arg| arg.global(true))
+}
+
+#[doc(hidden)]
+fn format_arg() -> Arg {
+    Arg::new("format")
+        .long("format")
+        .value_name("FORMAT")
+        .value_parser(PossibleValuesParser::new(
+            OutputFormat::ALL.map(OutputFormat::name),
+        ))
+        .help("The format of the findings (default: human, or github in GitHub Actions)")
+}
+
+#[doc(hidden)]
+fn check_args() -> [Arg; 3] {
+    [
+        format_arg(),
+        Arg::new("summary")
+            .long("summary")
+            .value_name("FORMAT")
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("text")
+            .value_parser(PossibleValuesParser::new(
+                SummaryFormat::ALL.map(SummaryFormat::name),
+            ))
+            .help("Also print coverage annotation statistics for each file"),
+        Arg::new("write-baseline")
+            .long("write-baseline")
+            .value_name("PATH")
+            .help("Write all the findings to a baseline file and exit"),
+    ]
+}
+
+#[doc(hidden)]
+fn fix_args() -> [Arg; 4] {
+    [
+        Arg::new("fix")
+            .long("fix")
+            .action(ArgAction::SetTrue)
+            .help("Fix wrong coverage annotations"),
+        Arg::new("regions")
+            .long("fix-regions")
+            .visible_alias("regions")
+            .value_name("LINES")
+            .num_args(0..=1)
+            .require_equals(true)
+            .value_parser(value_parser!(usize))
+            .help("Use regions for runs of at least this many uncovered lines (default: 5)"),
+        Arg::new("diff")
+            .long("diff")
+            .action(ArgAction::SetTrue)
+            .help("Print the fixes as a unified diff instead of modifying the files"),
+        Arg::new("patch")
+            .long("patch")
+            .value_name("PATH")
+            .help("Write the fixes to a patch file instead of modifying the files"),
+    ]
+}
+
+#[doc(hidden)]
+fn parse_location(location: &str) -> Result<(String, usize), String> {
+    location
+        .rsplit_once(':')
+        .and_then(|(file_name, line_number)| {
+            let line_number = line_number
+                .parse()
+                .ok()
+                .filter(|line_number| *line_number > 0)?;
+            Some((file_name.to_string(), line_number))
+        })
+        .ok_or_else(|| "expected FILE:LINE (e.g. src/lib.rs:123)".to_string())
+}
+
+// When invoked as `cargo coverage-annotations`, cargo passes the subcommand name as the first
+// argument, which is skipped so the program can also be invoked directly.
+#[doc(hidden)]
+pub fn process_args(config: &mut Config) -> Action {
+    let mut args: Vec<OsString> = std::env::args_os().collect();
+    if args.get(1).is_some_and(|arg| arg == "coverage-annotations") {
+        args.remove(1);
+    }
+    let matches = cli_command().get_matches_from(args);
+    apply_global_args(config, &matches);
+    if matches.subcommand().is_some() {
+        reject_root_args(&matches);
+    }
+    let action = match matches.subcommand() {
+        None => {
+            apply_fix_args(config, &matches);
+            apply_check_args(config, &matches);
+            Action::Check
+        }
+        Some(("check", matches)) => {
+            apply_check_args(config, matches);
+            Action::Check
+        }
+        Some(("fix", matches)) => {
+            config.fix = true;
+            apply_fix_args(config, matches);
+            apply_format_arg(config, matches);
+            Action::Check
+        }
+        Some(("report", matches)) => {
+            apply_format_arg(config, matches);
+            config.output = matches.get_one::<String>("output").cloned();
+            if config.format.is_human() {
+                usage_error("the report format must not be human or short");
+            }
+            Action::Check
+        }
+        Some(("stats", matches)) => {
+            config.summary = Some(if matches.get_flag("json") {
+                SummaryFormat::Json
+            } else {
+                SummaryFormat::Text
+            });
+            Action::Stats
+        }
+        Some(("explain", matches)) => {
+            let (file_name, line_number) = matches
+                .get_one::<(String, usize)>("location")
+                .unwrap()
+                .clone();
+            Action::Explain(file_name, line_number)
+        }
+        Some(("init", _)) => {
+            init_config_file(config);
+            std::process::exit(0);
+        }
+        Some(("completions", matches)) => {
+            let shell = *matches.get_one::<Shell>("shell").unwrap();
+            clap_complete::generate(
+                shell,
+                &mut cli_command(),
+                "cargo-coverage-annotations",
+                &mut std::io::stdout(),
+            );
+            std::process::exit(0);
+        }
+        Some(_) => unreachable!(),
+    };
+    if config.summary == Some(SummaryFormat::Json)
+        && !config.format.is_human()
+        && config.format != OutputFormat::Json
+    {
+        usage_error("--summary=json can only be combined with --format=json");
+    }
+    if config.diff && !config.format.is_human() {
+        usage_error("--diff can't be combined with --format, use --patch instead");
+    }
+    if matches.get_flag("print-config") {
+        print!("{}", config.to_toml());
+        std::process::exit(0);
+    }
+    action
+}
+
+#[doc(hidden)]
+fn reject_root_args(matches: &ArgMatches) {
+    for arg in check_args().iter().chain(fix_args().iter()) {
+        if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            usage_error(&format!(
+                "--{} must be given after the command name",
+                arg.get_long().unwrap()
+            ));
+        }
+    }
+}
+
+#[doc(hidden)]
+fn apply_global_args(config: &mut Config, matches: &ArgMatches) {
+    if let Some(flaky_policy) = matches.get_one::<String>("flaky") {
+        config.flaky_policy = flaky_policy.parse().unwrap();
+    }
+    if let Some(coverage) = matches.get_many::<String>("coverage") {
+        config.coverage = coverage.cloned().collect();
+    }
+    if let Some(sources) = matches.get_many::<String>("source") {
+        config.sources = sources.cloned().collect();
+    }
+    config.workspace = matches.get_flag("workspace");
+    if let Some(packages) = matches.get_many::<String>("package") {
+        config.packages = packages.cloned().collect();
+    }
+    if let Some(color) = matches.get_one::<String>("color") {
+        config.color = color.parse().unwrap();
+    }
+    if let Some(baseline) = matches.get_one::<String>("baseline") {
+        config.baseline = Some(baseline.clone());
+    }
+    config.changed_since = matches.get_one::<String>("changed-since").cloned();
+}
+
+#[doc(hidden)]
+fn apply_format_arg(config: &mut Config, matches: &ArgMatches) {
+    if let Some(format) = matches.get_one::<String>("format") {
+        config.format = format.parse().unwrap();
+    } else if !config.diff
+        && config.format == OutputFormat::Human
+        && std::env::var("GITHUB_ACTIONS").is_ok_and(|value| value == "true")
+    {
+        config.format = OutputFormat::Github;
+    }
+}
+
+#[doc(hidden)]
+fn apply_check_args(config: &mut Config, matches: &ArgMatches) {
+    apply_format_arg(config, matches);
+    if let Some(summary) = matches.get_one::<String>("summary") {
+        config.summary = Some(summary.parse().unwrap());
+    }
+    config.write_baseline = matches.get_one::<String>("write-baseline").cloned();
+}
+
+// These are applied before the format, so the `--diff` output isn't mixed with GitHub annotations.
+#[doc(hidden)]
+fn apply_fix_args(config: &mut Config, matches: &ArgMatches) {
+    if matches.value_source("regions").is_some() {
+        config.fix = true;
+        config.fix_region_lines = Some(
+            matches
+                .get_one::<usize>("regions")
+                .copied()
+                .unwrap_or(DEFAULT_FIX_REGION_LINES),
+        );
+    }
+    if matches.get_flag("fix") {
+        config.fix = true;
+    }
+    if matches.get_flag("diff") {
+        config.fix = true;
+        config.diff = true;
+    }
+    if let Some(patch) = matches.get_one::<String>("patch") {
+        config.fix = true;
+        config.patch = Some(patch.clone());
+    }
+}
+
+#[doc(hidden)]
+fn init_config_file(config: &Config) {
+    if Path::new(CONFIG_FILE_NAME).exists() {
+        eprintln!("{CONFIG_FILE_NAME}: already exists");
+        std::process::exit(ERROR_EXIT_STATUS);
+    }
+    write_file(CONFIG_FILE_NAME, &config.to_toml()).unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(ERROR_EXIT_STATUS);
+    });
+    eprintln!("{CONFIG_FILE_NAME}: created");
+}
+
+#[doc(hidden)]
+fn usage_error(message: &str) -> ! {
+    cli_command()
+        .error(ErrorKind::ArgumentConflict, message)
+        .exit()
+}
@@ -0,0 +1,471 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::report::{ColorChoice, OutputFormat};
+use crate::summary::SummaryFormat;
+use crate::{current_dir, line_annotation_name, FlakyPolicy, LineAnnotation, ERROR_EXIT_STATUS};
+use glob::{MatchOptions, Pattern};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml::{Table, Value};
+
+#[doc(hidden)]
+pub const CONFIG_FILE_NAME: &str = "coverage-annotations.toml";
+
+// The basic marker names; the region and file markers are formed by prefixing these with `BEGIN`,
+// `END` or `FILE`.
+#[doc(hidden)]
+pub const MARKER_NAMES: [&str; 4] = ["TESTED", "NOT TESTED", "MAYBE TESTED", "FLAKY TESTED"];
+
+#[doc(hidden)]
+const MARKER_PREFIXES: [&str; 3] = ["BEGIN ", "END ", "FILE "];
+
+#[doc(hidden)]
+pub const DEFAULT_FIX_REGION_LINES: usize = 5;
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Config {
+    pub flaky_policy: FlakyPolicy,
+    pub coverage: Vec<String>,
+    pub sources: Vec<String>,
+    pub packages: Vec<String>,
+    pub workspace: bool,
+    pub fix: bool,
+    pub fix_region_lines: Option<usize>,
+    pub diff: bool,
+    pub patch: Option<String>,
+    pub format: OutputFormat,
+    pub color: ColorChoice,
+    pub summary: Option<SummaryFormat>,
+    pub output: Option<String>,
+    pub baseline: Option<String>,
+    pub write_baseline: Option<String>,
+    pub changed_since: Option<String>,
+    pub markers: Vec<String>,
+    pub overrides: Vec<PathOverride>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            flaky_policy: FlakyPolicy::MaybeTested,
+            coverage: Vec::new(),
+            sources: Vec::new(),
+            packages: Vec::new(),
+            workspace: false,
+            fix: false,
+            fix_region_lines: None,
+            diff: false,
+            patch: None,
+            format: OutputFormat::Human,
+            color: ColorChoice::Auto,
+            summary: None,
+            output: None,
+            baseline: None,
+            write_baseline: None,
+            changed_since: None,
+            markers: MARKER_NAMES
+                .iter()
+                .map(|name| (*name).to_string())
+                .collect(),
+            overrides: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    // The policy used for a source file, where each matching override replaces the settings it
+    // specifies, in order.
+    #[doc(hidden)]
+    pub fn file_policy(&self, file_name: &str) -> FilePolicy {
+        let mut policy = FilePolicy {
+            is_ignored: false,
+            default_annotation: LineAnnotation::Tested(false),
+            flaky_policy: self.flaky_policy,
+            markers: self.markers.clone(),
+        };
+        let match_options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::default()
+        };
+        for path_override in &self.overrides {
+            let Some(relative_file_name) = Path::new(file_name)
+                .strip_prefix(&path_override.base_dir)
+                .ok()
+                .and_then(Path::to_str)
+            else {
+                continue;
+            };
+            if !path_override
+                .patterns
+                .iter()
+                .any(|pattern| pattern.matches_with(relative_file_name, match_options))
+            {
+                continue;
+            }
+            if let Some(is_ignored) = path_override.is_ignored {
+                policy.is_ignored = is_ignored;
+            }
+            if let Some(default_annotation) = path_override.default_annotation {
+                policy.default_annotation = default_annotation;
+            }
+            if let Some(flaky_policy) = path_override.flaky_policy {
+                policy.flaky_policy = flaky_policy;
+            }
+            if let Some(markers) = &path_override.markers {
+                policy.markers.clone_from(markers);
+            }
+        }
+        policy
+    }
+
+    // This uses the same keys as the configuration files, so its output can be used as one.
+    #[doc(hidden)]
+    pub fn to_toml(&self) -> String {
+        let mut table = Table::new();
+        table.insert("flaky".to_string(), self.flaky_policy.name().into());
+        table.insert("coverage".to_string(), self.coverage.clone().into());
+        table.insert("sources".to_string(), self.sources.clone().into());
+        table.insert("format".to_string(), self.format.name().into());
+        table.insert("color".to_string(), self.color.name().into());
+        if let Some(summary) = self.summary {
+            table.insert("summary".to_string(), summary.name().into());
+        }
+        if let Some(baseline) = &self.baseline {
+            table.insert("baseline".to_string(), baseline.clone().into());
+        }
+        table.insert("markers".to_string(), self.markers.clone().into());
+        if !self.overrides.is_empty() {
+            let overrides: Vec<Value> = self.overrides.iter().map(PathOverride::to_toml).collect();
+            table.insert("overrides".to_string(), overrides.into());
+        }
+        table.to_string()
+    }
+}
+
+// The settings which may be overridden for source files whose path (relative to the base
+// directory, which is the directory of the configuration that specified them) matches any of the
+// glob patterns.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct PathOverride {
+    pub base_dir: PathBuf,
+    pub patterns: Vec<Pattern>,
+    pub is_ignored: Option<bool>,
+    pub default_annotation: Option<LineAnnotation>,
+    pub flaky_policy: Option<FlakyPolicy>,
+    pub markers: Option<Vec<String>>,
+}
+
+impl PathOverride {
+    #[doc(hidden)]
+    fn to_toml(&self) -> Value {
+        let mut table = Table::new();
+        let paths: Vec<String> = self.patterns.iter().map(ToString::to_string).collect();
+        table.insert("paths".to_string(), paths.into());
+        if let Some(is_ignored) = self.is_ignored {
+            table.insert("ignore".to_string(), is_ignored.into());
+        }
+        if let Some(default_annotation) = self.default_annotation {
+            table.insert(
+                "default".to_string(),
+                line_annotation_name(default_annotation).into(),
+            );
+        }
+        if let Some(flaky_policy) = self.flaky_policy {
+            table.insert("flaky".to_string(), flaky_policy.name().into());
+        }
+        if let Some(markers) = &self.markers {
+            table.insert("markers".to_string(), markers.clone().into());
+        }
+        table.into()
+    }
+}
+
+// The default annotation applies to the lines which are neither annotated nor in a region.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct FilePolicy {
+    pub is_ignored: bool,
+    pub default_annotation: LineAnnotation,
+    pub flaky_policy: FlakyPolicy,
+    pub markers: Vec<String>,
+}
+
+impl FilePolicy {
+    // A marker is allowed if either its full name (e.g., `FILE NOT TESTED`) or its basic name
+    // (e.g., `NOT TESTED`) is listed.
+    #[doc(hidden)]
+    pub fn is_allowed_marker(&self, name: &str) -> bool {
+        let base_name = MARKER_PREFIXES
+            .iter()
+            .find_map(|prefix| name.strip_prefix(prefix))
+            .unwrap_or(name);
+        self.markers
+            .iter()
+            .any(|marker| marker == name || marker == base_name)
+    }
+}
+
+// The settings are taken from the `[workspace.metadata.coverage-annotations]` table of the enclosing
+// workspace manifest, then from the `[package.metadata.coverage-annotations]` table of the package
+// manifest in the current directory, and finally from the `coverage-annotations.toml` file in the
+// current directory, where later settings override earlier ones.
+#[doc(hidden)]
+pub fn load_config_files(config: &mut Config) {
+    let current_dir = current_dir();
+    for dir in current_dir.ancestors() {
+        let manifest_path = dir.join("Cargo.toml");
+        let Some(manifest) = read_toml_file(&manifest_path) else {
+            continue;
+        };
+        if manifest.contains_key("workspace") {
+            if let Some(metadata) = metadata_table(&manifest, "workspace") {
+                let context = format!(
+                    "{}: workspace.metadata.coverage-annotations.",
+                    manifest_path.display()
+                );
+                apply_config_table(config, &context, current_dir, dir, metadata);
+            }
+            break;
+        }
+    }
+    if let Some(manifest) = read_toml_file(Path::new("Cargo.toml")) {
+        if let Some(metadata) = metadata_table(&manifest, "package") {
+            apply_config_table(
+                config,
+                "Cargo.toml: package.metadata.coverage-annotations.",
+                current_dir,
+                current_dir,
+                metadata,
+            );
+        }
+    }
+    if let Some(table) = read_toml_file(Path::new(CONFIG_FILE_NAME)) {
+        apply_config_table(
+            config,
+            &format!("{CONFIG_FILE_NAME}: "),
+            current_dir,
+            current_dir,
+            &table,
+        );
+    }
+}
+
+#[doc(hidden)]
+fn read_toml_file(path: &Path) -> Option<Table> {
+    let text = fs::read_to_string(path).ok()?;
+    Some(text.parse().unwrap_or_else(|error| {
+        eprintln!("{}: {error}", path.display());
+        std::process::exit(ERROR_EXIT_STATUS);
+    }))
+}
+
+#[doc(hidden)]
+fn metadata_table<'a>(manifest: &'a Table, section: &str) -> Option<&'a Table> {
+    manifest
+        .get(section)?
+        .get("metadata")?
+        .get("coverage-annotations")?
+        .as_table()
+}
+
+// The context is the file name and the prefix of the keys, for error messages. The paths are
+// relative to the base directory, which is either the current directory or one of its ancestors.
+#[doc(hidden)]
+fn apply_config_table(
+    config: &mut Config,
+    context: &str,
+    current_dir: &Path,
+    base_dir: &Path,
+    table: &Table,
+) {
+    let base_path = |path: String| config_path(current_dir, base_dir, path);
+    for (key, value) in table {
+        match key.as_str() {
+            "flaky" => config.flaky_policy = parsed_value(context, key, value),
+            "coverage" => {
+                config.coverage = strings_value(context, key, value)
+                    .into_iter()
+                    .map(base_path)
+                    .collect();
+            }
+            "sources" => {
+                config.sources = strings_value(context, key, value)
+                    .into_iter()
+                    .map(base_path)
+                    .collect();
+            }
+            "format" => config.format = parsed_value(context, key, value),
+            "color" => config.color = parsed_value(context, key, value),
+            "summary" => config.summary = Some(parsed_value(context, key, value)),
+            "baseline" => config.baseline = Some(base_path(string_value(context, key, value))),
+            "markers" => config.markers = markers_value(context, key, value),
+            "overrides" => {
+                let Some(overrides) = value.as_array() else {
+                    eprintln!("{context}{key} must be an array of tables");
+                    std::process::exit(ERROR_EXIT_STATUS);
+                };
+                for (index, path_override) in overrides.iter().enumerate() {
+                    let context = format!("{context}{key}[{index}].");
+                    config
+                        .overrides
+                        .push(path_override_value(&context, base_dir, path_override));
+                }
+            }
+            _ => {
+                eprintln!("{context}{key}: unknown setting; valid settings are flaky, coverage, sources, format, color, summary, baseline, markers and overrides");
+                std::process::exit(ERROR_EXIT_STATUS);
+            }
+        }
+    }
+}
+
+// A relative path in a configuration whose base directory is an ancestor of the current directory
+// is made relative to the current directory by prefixing it with `..` components, which keeps glob
+// patterns working.
+#[doc(hidden)]
+fn config_path(current_dir: &Path, base_dir: &Path, path: String) -> String {
+    if Path::new(&path).is_absolute() {
+        return path;
+    }
+    match current_dir.strip_prefix(base_dir) {
+        Ok(relative_dir) => "../".repeat(relative_dir.components().count()) + &path,
+        Err(_) => path,
+    }
+}
+
+#[doc(hidden)]
+fn path_override_value(context: &str, base_dir: &Path, value: &Value) -> PathOverride {
+    let Some(table) = value.as_table() else {
+        eprintln!("{context} must be a table");
+        std::process::exit(ERROR_EXIT_STATUS);
+    };
+    let mut path_override = PathOverride {
+        base_dir: base_dir.to_path_buf(),
+        patterns: Vec::new(),
+        is_ignored: None,
+        default_annotation: None,
+        flaky_policy: None,
+        markers: None,
+    };
+    for (key, value) in table {
+        match key.as_str() {
+            "paths" => {
+                path_override.patterns = strings_value(context, key, value)
+                    .iter()
+                    .map(|path| {
+                        Pattern::new(path).unwrap_or_else(|error| {
+                            eprintln!("{context}{key}: invalid path pattern {path}: {error}");
+                            std::process::exit(ERROR_EXIT_STATUS);
+                        })
+                    })
+                    .collect();
+            }
+            "ignore" => {
+                path_override.is_ignored = Some(value.as_bool().unwrap_or_else(|| {
+                    eprintln!("{context}{key} must be a boolean");
+                    std::process::exit(ERROR_EXIT_STATUS);
+                }));
+            }
+            "default" => {
+                path_override.default_annotation =
+                    Some(match string_value(context, key, value).as_str() {
+                        "TESTED" => LineAnnotation::Tested(false),
+                        "NOT TESTED" => LineAnnotation::NotTested(false),
+                        "MAYBE TESTED" => LineAnnotation::MaybeTested(false),
+                        "FLAKY TESTED" => LineAnnotation::FlakyTested(false),
+                        _ => {
+                            eprintln!(
+                                "{context}{key}: invalid value {value}; valid values are {}",
+                                MARKER_NAMES.join(", ")
+                            );
+                            std::process::exit(ERROR_EXIT_STATUS);
+                        }
+                    });
+            }
+            "flaky" => path_override.flaky_policy = Some(parsed_value(context, key, value)),
+            "markers" => path_override.markers = Some(markers_value(context, key, value)),
+            _ => {
+                eprintln!("{context}{key}: unknown setting; valid settings are paths, ignore, default, flaky and markers");
+                std::process::exit(ERROR_EXIT_STATUS);
+            }
+        }
+    }
+    if path_override.patterns.is_empty() {
+        eprintln!("{context}paths must list at least one path pattern");
+        std::process::exit(ERROR_EXIT_STATUS);
+    }
+    path_override
+}
+
+// Markers may be listed by their basic name (e.g., `NOT TESTED`), which allows all their forms, or
+// by a specific form (e.g., `FILE NOT TESTED`).
+#[doc(hidden)]
+fn markers_value(context: &str, key: &str, value: &Value) -> Vec<String> {
+    let markers = strings_value(context, key, value);
+    for marker in &markers {
+        let base_name = MARKER_PREFIXES
+            .iter()
+            .find_map(|prefix| marker.strip_prefix(prefix))
+            .unwrap_or(marker);
+        if !MARKER_NAMES.contains(&base_name) {
+            eprintln!(
+                "{context}{key}: unknown marker \"{marker}\"; valid markers are {} (optionally prefixed by BEGIN, END or FILE)",
+                MARKER_NAMES.join(", ")
+            );
+            std::process::exit(ERROR_EXIT_STATUS);
+        }
+    }
+    markers
+}
+
+#[doc(hidden)]
+fn parsed_value<T: FromStr>(context: &str, key: &str, value: &Value) -> T {
+    string_value(context, key, value)
+        .parse()
+        .unwrap_or_else(|_| {
+            eprintln!("{context}{key}: invalid value {value}");
+            std::process::exit(ERROR_EXIT_STATUS);
+        })
+}
+
+#[doc(hidden)]
+fn string_value(context: &str, key: &str, value: &Value) -> String {
+    value.as_str().map_or_else(
+        || {
+            eprintln!("{context}{key} must be a string");
+            std::process::exit(ERROR_EXIT_STATUS);
+        },
+        str::to_string,
+    )
+}
+
+#[doc(hidden)]
+fn strings_value(context: &str, key: &str, value: &Value) -> Vec<String> {
+    let strings = value.as_array().and_then(|array| {
+        array
+            .iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect::<Option<Vec<String>>>()
+    });
+    strings.unwrap_or_else(|| {
+        eprintln!("{context}{key} must be an array of strings");
+        std::process::exit(ERROR_EXIT_STATUS);
+    })
+}
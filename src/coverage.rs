@@ -0,0 +1,533 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::error::{path_str, CoverageEntry, Error};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use xml::attribute::OwnedAttribute;
+use xml::common::Position;
+use xml::reader::{EventReader, XmlEvent};
+
+#[doc(hidden)]
+pub fn is_coverage_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("xml" | "info" | "lcov" | "json")
+    )
+}
+
+// The hits of each line are added to any hits collected from other coverage files. Invalid entries
+// are reported and skipped, so the rest of the coverage file is still used. Explicitly given
+// coverage files must be in a known format, while crawled ones are skipped if they aren't.
+#[doc(hidden)]
+pub fn collect_coverage_file_annotations(
+    path: &Path,
+    is_explicit: bool,
+    coverage_annotations: &mut BTreeMap<String, HashMap<i32, u64>>,
+    errors: &mut Vec<Error>,
+) {
+    let report_name = match path_str(path) {
+        Ok(report_name) => report_name,
+        Err(error) => {
+            errors.push(error);
+            return;
+        }
+    };
+    let file = match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(error) => {
+            errors.push(Error::Read {
+                file_name: report_name.to_string(),
+                error,
+            });
+            return;
+        }
+    };
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("xml") => {
+            collect_cobertura_annotations(report_name, file, coverage_annotations, errors);
+        }
+        Some("info" | "lcov") => {
+            collect_lcov_annotations(report_name, file, coverage_annotations, errors);
+        }
+        Some("json") => {
+            collect_llvm_cov_annotations(
+                report_name,
+                is_explicit,
+                file,
+                coverage_annotations,
+                errors,
+            );
+        }
+        _ => {}
+    }
+}
+
+#[doc(hidden)]
+fn collect_cobertura_annotations(
+    report_name: &str,
+    file: BufReader<File>,
+    coverage_annotations: &mut BTreeMap<String, HashMap<i32, u64>>,
+    errors: &mut Vec<Error>,
+) {
+    let mut parser = EventReader::new(file);
+    let mut file_name: Option<String> = None;
+    let mut sources: Vec<String> = vec![String::new()];
+    let mut collect_source = false;
+    loop {
+        let event = match parser.next() {
+            Ok(XmlEvent::EndDocument) => return,
+            Ok(event) => event,
+            Err(error) => {
+                let entry = coverage_entry(
+                    report_name,
+                    error.position().row,
+                    "XML document".to_string(),
+                );
+                errors.push(Error::InvalidCoverageEntry {
+                    entry,
+                    reason: error.msg().to_string(),
+                });
+                return;
+            }
+        };
+        let entry = |element: &str, attributes: &[OwnedAttribute]| {
+            coverage_entry(
+                report_name,
+                parser.position().row,
+                element_text(element, attributes),
+            )
+        };
+        match event {
+            XmlEvent::StartElement {
+                ref name,
+                ref attributes,
+                ..
+            } => {
+                collect_source = name.local_name == "source";
+                if name.local_name == "class" {
+                    if let Some(attribute) = attributes
+                        .iter()
+                        .find(|attribute| attribute.name.local_name == "filename")
+                    {
+                        file_name = match canonical_file_name(&sources, &attribute.value) {
+                            Ok(file_name) => {
+                                coverage_annotations.entry(file_name.clone()).or_default();
+                                Some(file_name)
+                            }
+                            Err(reason) => {
+                                errors.push(Error::InvalidCoverageEntry {
+                                    entry: entry("class", attributes),
+                                    reason: reason.to_string(),
+                                });
+                                None
+                            }
+                        };
+                    }
+                }
+                if let (Some(file_name), "line") = (&file_name, name.local_name.as_str()) {
+                    match cobertura_line_hits(attributes) {
+                        Ok(Some((line_number, hits_count))) => collect_line_hits(
+                            coverage_annotations.get_mut(file_name).unwrap(),
+                            line_number,
+                            hits_count,
+                        ),
+                        Ok(None) => {}
+                        Err(reason) => errors.push(Error::InvalidCoverageEntry {
+                            entry: entry("line", attributes),
+                            reason,
+                        }),
+                    }
+                }
+            }
+            XmlEvent::Characters(mut string) if collect_source => {
+                if !string.ends_with('/') {
+                    string.push('/');
+                }
+                sources.push(string);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Lines without a (positive) number are ignored, as are lines outside of a (resolved) class. A
+// numbered line must also have a hits count.
+#[doc(hidden)]
+fn cobertura_line_hits(attributes: &[OwnedAttribute]) -> Result<Option<(i32, i64)>, String> {
+    let mut line_number = -1;
+    let mut hits_count = None;
+    for attribute in attributes {
+        if attribute.name.local_name == "number" {
+            line_number = attribute
+                .value
+                .parse()
+                .map_err(|_| format!("invalid line number `{}`", attribute.value))?;
+        } else if attribute.name.local_name == "hits" {
+            hits_count = Some(
+                attribute
+                    .value
+                    .parse()
+                    .map_err(|_| format!("invalid hits count `{}`", attribute.value))?,
+            );
+        }
+    }
+    if line_number <= 0 {
+        return Ok(None);
+    }
+    let hits_count = hits_count.ok_or_else(|| "missing hits count".to_string())?;
+    Ok(Some((line_number, hits_count)))
+}
+
+#[doc(hidden)]
+fn element_text(element: &str, attributes: &[OwnedAttribute]) -> String {
+    let mut text = format!("<{element}");
+    for attribute in attributes {
+        write!(
+            text,
+            " {}=\"{}\"",
+            attribute.name.local_name, attribute.value
+        )
+        .unwrap();
+    }
+    text.push('>');
+    text
+}
+
+// The positions of the XML parser are 0-based.
+#[doc(hidden)]
+fn coverage_entry(report_name: &str, row: u64, element: String) -> CoverageEntry {
+    CoverageEntry {
+        file_name: report_name.to_string(),
+        line_number: usize::try_from(row).ok().map(|row| row + 1),
+        element,
+    }
+}
+
+#[doc(hidden)]
+fn collect_lcov_annotations(
+    report_name: &str,
+    file: BufReader<File>,
+    coverage_annotations: &mut BTreeMap<String, HashMap<i32, u64>>,
+    errors: &mut Vec<Error>,
+) {
+    let mut sources: Vec<String> = vec![String::new()];
+    if let Some(parent) = Path::new(report_name).parent().and_then(Path::to_str) {
+        sources.push(format!("{parent}/"));
+    }
+    let mut file_name: Option<String> = None;
+    for (line_index, line) in file.lines().enumerate() {
+        let line_text = match line {
+            Ok(line_text) => line_text,
+            Err(error) => {
+                errors.push(Error::Read {
+                    file_name: report_name.to_string(),
+                    error,
+                });
+                return;
+            }
+        };
+        let line_text = line_text.trim();
+        let entry = || CoverageEntry {
+            file_name: report_name.to_string(),
+            line_number: Some(line_index + 1),
+            element: format!("`{line_text}`"),
+        };
+        if let Some(source_file) = line_text.strip_prefix("SF:") {
+            file_name = match canonical_file_name(&sources, source_file) {
+                Ok(file_name) => {
+                    coverage_annotations.entry(file_name.clone()).or_default();
+                    Some(file_name)
+                }
+                Err(reason) => {
+                    errors.push(Error::InvalidCoverageEntry {
+                        entry: entry(),
+                        reason: reason.to_string(),
+                    });
+                    None
+                }
+            };
+        } else if let Some(line_data) = line_text.strip_prefix("DA:") {
+            let Some(file_name) = &file_name else {
+                continue;
+            };
+            match lcov_line_hits(line_data) {
+                Some((line_number, hits_count)) => {
+                    if line_number > 0 {
+                        collect_line_hits(
+                            coverage_annotations.get_mut(file_name).unwrap(),
+                            line_number,
+                            hits_count,
+                        );
+                    }
+                }
+                None => errors.push(Error::InvalidCoverageEntry {
+                    entry: entry(),
+                    reason: "expected `DA:<line number>,<hits count>`".to_string(),
+                }),
+            }
+        } else if line_text == "end_of_record" {
+            file_name = None;
+        }
+    }
+}
+
+#[doc(hidden)]
+fn lcov_line_hits(line_data: &str) -> Option<(i32, i64)> {
+    let mut fields = line_data.split(',');
+    let line_number = fields.next()?.parse().ok()?;
+    let hits_count = fields.next()?.parse().ok()?;
+    Some((line_number, hits_count))
+}
+
+#[doc(hidden)]
+struct Segment {
+    line: i32,
+    count: i64,
+    has_count: bool,
+    is_region_entry: bool,
+    is_gap_region: bool,
+}
+
+#[doc(hidden)]
+fn collect_llvm_cov_annotations(
+    report_name: &str,
+    is_explicit: bool,
+    file: BufReader<File>,
+    coverage_annotations: &mut BTreeMap<String, HashMap<i32, u64>>,
+    errors: &mut Vec<Error>,
+) {
+    let invalid_entry =
+        |line_number: Option<usize>, element: String, reason: String| Error::InvalidCoverageEntry {
+            entry: CoverageEntry {
+                file_name: report_name.to_string(),
+                line_number,
+                element,
+            },
+            reason,
+        };
+    let export: Value = match serde_json::from_reader(file) {
+        Ok(export) => export,
+        Err(error) => {
+            errors.push(invalid_entry(
+                Some(error.line()),
+                "JSON document".to_string(),
+                error.to_string(),
+            ));
+            return;
+        }
+    };
+    if export["type"] != "llvm.coverage.json.export" {
+        if is_explicit {
+            errors.push(Error::InvalidFile {
+                file_name: report_name.to_string(),
+                reason: "not an llvm-cov JSON export".to_string(),
+            });
+        }
+        return;
+    }
+    let mut sources: Vec<String> = vec![String::new()];
+    if let Some(parent) = Path::new(report_name).parent().and_then(Path::to_str) {
+        sources.push(format!("{parent}/"));
+    }
+    let files = export["data"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|data| data["files"].as_array().into_iter().flatten());
+    for file in files {
+        let source_file = file["filename"].as_str().unwrap_or_default();
+        let element = format!("file `{source_file}`");
+        let file_name = match canonical_file_name(&sources, source_file) {
+            Ok(file_name) => file_name,
+            Err(reason) => {
+                errors.push(invalid_entry(None, element, reason.to_string()));
+                continue;
+            }
+        };
+        // The file is known to be covered even if its segments can't be used.
+        let coverage_file_annotations = coverage_annotations.entry(file_name).or_default();
+        let Some(segments) = file["segments"].as_array().and_then(|segments| {
+            segments
+                .iter()
+                .map(parse_segment)
+                .collect::<Option<Vec<Segment>>>()
+        }) else {
+            errors.push(invalid_entry(
+                None,
+                element,
+                "expected segments of [line, column, count, has count, is region entry, ...]"
+                    .to_string(),
+            ));
+            continue;
+        };
+        collect_segments_hits(&segments, coverage_file_annotations);
+    }
+}
+
+#[doc(hidden)]
+fn parse_segment(segment: &Value) -> Option<Segment> {
+    // [line, column, count, has count, is region entry, is gap region (only since version 2)]
+    let fields = segment.as_array()?;
+    Some(Segment {
+        line: i32::try_from(fields.first()?.as_u64()?).ok()?,
+        count: i64::try_from(fields.get(2)?.as_u64()?).unwrap_or(i64::MAX),
+        has_count: fields.get(3)?.as_bool()?,
+        is_region_entry: fields.get(4)?.as_bool()?,
+        is_gap_region: fields.get(5).and_then(Value::as_bool).unwrap_or(false),
+    })
+}
+
+// This mirrors the way `llvm-cov` itself computes line coverage (see `LineCoverageIterator` and
+// `LineCoverageStats` in LLVM's `CoverageMapping.cpp`), so the results are identical to what
+// `llvm-cov show` and `llvm-cov report` display.
+#[doc(hidden)]
+fn collect_segments_hits(segments: &[Segment], coverage_file_annotations: &mut HashMap<i32, u64>) {
+    let (Some(first_segment), Some(last_segment)) = (segments.first(), segments.last()) else {
+        return;
+    };
+    let mut wrapped_segment: Option<&Segment> = None;
+    let mut next_segment = 0;
+    for line_number in first_segment.line..=last_segment.line {
+        let first_line_segment = next_segment;
+        while next_segment < segments.len() && segments[next_segment].line == line_number {
+            next_segment += 1;
+        }
+        let line_segments = &segments[first_line_segment..next_segment];
+        if let Some(hits_count) = line_hits(line_segments, wrapped_segment) {
+            collect_line_hits(coverage_file_annotations, line_number, hits_count);
+        }
+        if let Some(last_line_segment) = line_segments.last() {
+            wrapped_segment = Some(last_line_segment);
+        }
+    }
+}
+
+#[doc(hidden)]
+fn line_hits(line_segments: &[Segment], wrapped_segment: Option<&Segment>) -> Option<i64> {
+    let is_start_of_region =
+        |segment: &&Segment| !segment.is_gap_region && segment.has_count && segment.is_region_entry;
+    let regions_count = line_segments.iter().filter(is_start_of_region).count();
+    let is_start_of_skipped_region = line_segments
+        .first()
+        .is_some_and(|segment| !segment.has_count && segment.is_region_entry);
+    let is_mapped = (!is_start_of_skipped_region
+        && (wrapped_segment.is_some_and(|segment| segment.has_count) || regions_count > 0))
+        || line_segments
+            .iter()
+            .any(|segment| segment.is_region_entry && segment.has_count);
+    if !is_mapped {
+        return None;
+    }
+    let wrapped_count = wrapped_segment.map_or(0, |segment| segment.count);
+    Some(
+        line_segments
+            .iter()
+            .filter(is_start_of_region)
+            .map(|segment| segment.count)
+            .fold(wrapped_count, i64::max),
+    )
+}
+
+#[doc(hidden)]
+fn collect_line_hits(
+    coverage_file_annotations: &mut HashMap<i32, u64>,
+    line_number: i32,
+    hits_count: i64,
+) {
+    let line_hits = coverage_file_annotations.entry(line_number).or_default();
+    *line_hits = line_hits.saturating_add(hits_count.max(0).unsigned_abs());
+}
+
+// The error is the reason the file name can't be used.
+#[doc(hidden)]
+fn canonical_file_name(sources: &[String], file_name: &str) -> Result<String, &'static str> {
+    for source in sources {
+        let mut path = PathBuf::from(source);
+        path.push(file_name);
+        if let Ok(canonical) = fs::canonicalize(path) {
+            return canonical
+                .to_str()
+                .map(str::to_string)
+                .ok_or("the source file path is not valid UTF-8");
+        }
+    }
+    Err("no such source file")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collect_segments_hits, line_hits, Segment};
+    use std::collections::HashMap;
+
+    fn segment(line: i32, count: i64, has_count: bool, is_region_entry: bool) -> Segment {
+        Segment {
+            line,
+            count,
+            has_count,
+            is_region_entry,
+            is_gap_region: false,
+        }
+    }
+
+    fn segments_hits(segments: &[Segment]) -> Vec<(i32, u64)> {
+        let mut coverage_file_annotations = HashMap::new();
+        collect_segments_hits(segments, &mut coverage_file_annotations);
+        let mut hits: Vec<(i32, u64)> = coverage_file_annotations.into_iter().collect();
+        hits.sort_unstable();
+        hits
+    }
+
+    #[test]
+    fn region_entries_use_the_maximal_count() {
+        let segments = [segment(1, 3, true, true), segment(1, 7, true, true)];
+        assert_eq!(line_hits(&segments, None), Some(7));
+        let wrapped = segment(1, 9, true, true);
+        assert_eq!(line_hits(&segments, Some(&wrapped)), Some(9));
+    }
+
+    #[test]
+    fn skipped_regions_are_not_mapped() {
+        let wrapped = segment(1, 3, true, true);
+        let segments = [segment(2, 0, false, true)];
+        assert_eq!(line_hits(&segments, Some(&wrapped)), None);
+        assert_eq!(line_hits(&[], None), None);
+    }
+
+    #[test]
+    fn gap_regions_do_not_count() {
+        let gap = Segment {
+            is_gap_region: true,
+            ..segment(2, 0, true, true)
+        };
+        let segments = [segment(1, 2, true, true), gap, segment(4, 0, false, false)];
+        assert_eq!(segments_hits(&segments), [(1, 2), (2, 2), (3, 0), (4, 0)]);
+    }
+
+    #[test]
+    fn wrapped_segments_cover_the_following_lines() {
+        let segments = [
+            segment(1, 3, true, true),
+            segment(2, 5, true, true),
+            segment(4, 0, false, false),
+        ];
+        assert_eq!(segments_hits(&segments), [(1, 3), (2, 5), (3, 5), (4, 5)]);
+    }
+}
@@ -0,0 +1,267 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::fix::find_marker;
+use crate::relative_file_name;
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs;
+
+#[doc(hidden)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    #[doc(hidden)]
+    pub const fn id(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DiagnosticKind {
+    WrongTested,
+    WrongNotTested,
+    NonExecutableAnnotation,
+    WrongFileNotTested,
+    MissingFileNotTested,
+    DisallowedAnnotation,
+    RedundantAnnotation,
+    NestedRegion,
+    RepeatedFileAnnotation,
+    AnnotationInUntestedFile,
+    ObsoleteAnnotation,
+    ToolError,
+}
+
+impl DiagnosticKind {
+    #[doc(hidden)]
+    pub const ALL: [Self; 12] = [
+        Self::WrongTested,
+        Self::WrongNotTested,
+        Self::NonExecutableAnnotation,
+        Self::WrongFileNotTested,
+        Self::MissingFileNotTested,
+        Self::DisallowedAnnotation,
+        Self::RedundantAnnotation,
+        Self::NestedRegion,
+        Self::RepeatedFileAnnotation,
+        Self::AnnotationInUntestedFile,
+        Self::ObsoleteAnnotation,
+        Self::ToolError,
+    ];
+
+    #[doc(hidden)]
+    pub const fn id(self) -> &'static str {
+        match self {
+            Self::WrongTested => "wrong-tested",
+            Self::WrongNotTested => "wrong-not-tested",
+            Self::NonExecutableAnnotation => "non-executable-annotation",
+            Self::WrongFileNotTested => "wrong-file-not-tested",
+            Self::MissingFileNotTested => "missing-file-not-tested",
+            Self::DisallowedAnnotation => "disallowed-annotation",
+            Self::RedundantAnnotation => "redundant-annotation",
+            Self::NestedRegion => "nested-region",
+            Self::RepeatedFileAnnotation => "repeated-file-annotation",
+            Self::AnnotationInUntestedFile => "annotation-in-untested-file",
+            Self::ObsoleteAnnotation => "obsolete-annotation",
+            Self::ToolError => "tool-error",
+        }
+    }
+
+    #[doc(hidden)]
+    pub const fn description(self) -> &'static str {
+        match self {
+            Self::WrongTested => "A line which is not covered is not annotated as NOT TESTED.",
+            Self::WrongNotTested => "A line which is covered is annotated as NOT TESTED.",
+            Self::NonExecutableAnnotation => {
+                "A line which is not executable has an explicit coverage annotation."
+            }
+            Self::WrongFileNotTested => "A file which is covered is annotated as FILE NOT TESTED.",
+            Self::MissingFileNotTested => {
+                "A file which is not covered at all is not annotated as FILE NOT TESTED."
+            }
+            Self::DisallowedAnnotation => {
+                "A coverage annotation is not in the configured markers vocabulary."
+            }
+            Self::RedundantAnnotation => {
+                "A line is annotated the same way as the region containing it."
+            }
+            Self::NestedRegion => "A BEGIN or END region annotation is nested in another region.",
+            Self::RepeatedFileAnnotation => "A file has more than one FILE annotation.",
+            Self::AnnotationInUntestedFile => {
+                "A line has a coverage annotation in a file which is NOT/MAYBE/FLAKY TESTED."
+            }
+            Self::ObsoleteAnnotation => "The obsolete APPEARS TESTED annotation is used.",
+            Self::ToolError => {
+                "A file could not be read, written or parsed, so it was not (fully) checked."
+            }
+        }
+    }
+
+    // Only wrong annotations (and tool errors) cause a failure exit status; the rest are sloppy but
+    // harmless.
+    #[doc(hidden)]
+    pub const fn severity(self) -> Severity {
+        match self {
+            Self::WrongTested
+            | Self::WrongNotTested
+            | Self::NonExecutableAnnotation
+            | Self::WrongFileNotTested
+            | Self::MissingFileNotTested
+            | Self::DisallowedAnnotation
+            | Self::ToolError => Severity::Error,
+            Self::RedundantAnnotation
+            | Self::NestedRegion
+            | Self::RepeatedFileAnnotation
+            | Self::AnnotationInUntestedFile
+            | Self::ObsoleteAnnotation => Severity::Warning,
+        }
+    }
+}
+
+// The expected and actual annotations are the marker names (e.g. `NOT TESTED`), where `None` means
+// there should be (or is) no annotation at all. The hits are only known for executable lines.
+#[doc(hidden)]
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub file_name: String,
+    pub line_number: Option<usize>,
+    pub actual: Option<&'static str>,
+    pub expected: Option<&'static str>,
+    pub hits: Option<u64>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    #[doc(hidden)]
+    pub fn new(
+        kind: DiagnosticKind,
+        file_name: &str,
+        line_number: Option<usize>,
+        actual: Option<&'static str>,
+        expected: Option<&'static str>,
+        hits: Option<u64>,
+        message: String,
+    ) -> Self {
+        Self {
+            kind,
+            file_name: file_name.to_string(),
+            line_number,
+            actual,
+            expected,
+            hits,
+            message,
+        }
+    }
+
+    #[doc(hidden)]
+    pub const fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+
+    // The source line the diagnostic is about, and the byte range of the annotation comment in it,
+    // if there is one. This reads the file as it is now, which matches the diagnostic since fixed
+    // files are re-collected.
+    #[doc(hidden)]
+    pub fn source_line(&self) -> Option<(String, Option<(usize, usize)>)> {
+        let line_number = self.line_number?;
+        let text = fs::read_to_string(&self.file_name).ok()?;
+        let line = text.lines().nth(line_number - 1)?.to_string();
+        let comment_range = self
+            .actual
+            .and_then(|name| find_marker(&line, name))
+            .map(|(_, comment_range)| comment_range);
+        Some((line, comment_range))
+    }
+
+    // The 1-based start and (exclusive) end character columns of the annotation comment.
+    #[doc(hidden)]
+    pub fn comment_columns(&self) -> Option<(usize, usize)> {
+        let (line, Some((start, end))) = self.source_line()? else {
+            return None;
+        };
+        Some((
+            line[..start].chars().count() + 1,
+            line[..end].chars().count() + 1,
+        ))
+    }
+
+    // This identifies the finding across runs, even if lines are added or removed elsewhere in the
+    // file, by using the line's text (ignoring white space) rather than its number.
+    #[doc(hidden)]
+    pub fn fingerprint(&self) -> String {
+        let line_text = self.source_line().map_or_else(String::new, |(line, _)| {
+            line.split_whitespace().collect::<Vec<&str>>().join(" ")
+        });
+        format!(
+            "{:016x}",
+            fnv_hash(&[
+                relative_file_name(&self.file_name).as_str(),
+                self.kind.id(),
+                &line_text
+            ])
+        )
+    }
+
+    #[doc(hidden)]
+    pub fn to_json(&self) -> Value {
+        json!({
+            "kind": self.kind.id(),
+            "severity": self.severity().id(),
+            "file": self.file_name,
+            "line": self.line_number,
+            "actual": self.actual,
+            "expected": self.expected,
+            "hits": self.hits,
+            "message": self.message,
+        })
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line_number {
+            Some(line_number) => write!(
+                formatter,
+                "{}:{}: {}",
+                self.file_name, line_number, self.message
+            ),
+            None => write!(formatter, "{}: {}", self.file_name, self.message),
+        }
+    }
+}
+
+// The standard library hashers are not guaranteed to be stable across releases, so we use FNV-1a.
+#[doc(hidden)]
+pub fn fnv_hash(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for byte in part.bytes().chain(std::iter::once(0)) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
@@ -0,0 +1,239 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use std::fmt::Write;
+
+#[doc(hidden)]
+const CONTEXT_LINES: usize = 3;
+
+#[doc(hidden)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum DiffLine {
+    Same(usize, usize),
+    Removed(usize),
+    Added(usize),
+}
+
+#[doc(hidden)]
+pub fn unified_diff(file_name: &str, old_text: &str, new_text: &str) -> String {
+    let old_lines: Vec<&str> = old_text.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new_text.split_inclusive('\n').collect();
+    let diff_lines = diff_lines(&old_lines, &new_lines);
+    let mut patch = format!("--- a/{file_name}\n+++ b/{file_name}\n");
+    let mut start = 0;
+    while let Some(first_change) =
+        (start..diff_lines.len()).find(|index| !matches!(diff_lines[*index], DiffLine::Same(_, _)))
+    {
+        // A hunk continues as long as the unchanged lines between changes are shared context.
+        let mut last_change = first_change;
+        let mut index = first_change + 1;
+        while index < diff_lines.len() {
+            if !matches!(diff_lines[index], DiffLine::Same(_, _)) {
+                last_change = index;
+            } else if index - last_change > 2 * CONTEXT_LINES {
+                break;
+            }
+            index += 1;
+        }
+        let hunk_start = first_change.saturating_sub(CONTEXT_LINES);
+        let hunk_end = (last_change + 1 + CONTEXT_LINES).min(diff_lines.len());
+        write_hunk(
+            &mut patch,
+            &old_lines,
+            &new_lines,
+            &diff_lines,
+            hunk_start,
+            hunk_end,
+        );
+        start = hunk_end;
+    }
+    patch
+}
+
+#[doc(hidden)]
+fn write_hunk(
+    patch: &mut String,
+    old_lines: &[&str],
+    new_lines: &[&str],
+    diff_lines: &[DiffLine],
+    hunk_start: usize,
+    hunk_end: usize,
+) {
+    let (old_start, new_start) =
+        diff_lines[..hunk_start]
+            .iter()
+            .fold((0, 0), |(old, new), diff_line| match diff_line {
+                DiffLine::Same(_, _) => (old + 1, new + 1),
+                DiffLine::Removed(_) => (old + 1, new),
+                DiffLine::Added(_) => (old, new + 1),
+            });
+    let hunk = &diff_lines[hunk_start..hunk_end];
+    let old_count = hunk
+        .iter()
+        .filter(|diff_line| !matches!(diff_line, DiffLine::Added(_)))
+        .count();
+    let new_count = hunk
+        .iter()
+        .filter(|diff_line| !matches!(diff_line, DiffLine::Removed(_)))
+        .count();
+    writeln!(
+        patch,
+        "@@ -{} +{} @@",
+        hunk_range(old_start, old_count),
+        hunk_range(new_start, new_count)
+    )
+    .unwrap();
+    for diff_line in hunk {
+        let (prefix, line) = match *diff_line {
+            DiffLine::Same(old_index, _) => (' ', old_lines[old_index]),
+            DiffLine::Removed(old_index) => ('-', old_lines[old_index]),
+            DiffLine::Added(new_index) => ('+', new_lines[new_index]),
+        };
+        patch.push(prefix);
+        patch.push_str(line);
+        if !line.ends_with('\n') {
+            patch.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+#[doc(hidden)]
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+// This is the basic Myers algorithm, applied after skipping the common prefix and suffix. The
+// fixes only touch a few lines, so the number of differences is small.
+#[doc(hidden)]
+fn diff_lines(old_lines: &[&str], new_lines: &[&str]) -> Vec<DiffLine> {
+    let prefix_length = old_lines
+        .iter()
+        .zip(new_lines)
+        .take_while(|(old_line, new_line)| old_line == new_line)
+        .count();
+    let suffix_length = old_lines[prefix_length..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix_length..].iter().rev())
+        .take_while(|(old_line, new_line)| old_line == new_line)
+        .count();
+    let old_middle = &old_lines[prefix_length..old_lines.len() - suffix_length];
+    let new_middle = &new_lines[prefix_length..new_lines.len() - suffix_length];
+
+    let mut diff_lines: Vec<DiffLine> = (0..prefix_length)
+        .map(|index| DiffLine::Same(index, index))
+        .collect();
+    for diff_line in myers_diff(old_middle, new_middle) {
+        diff_lines.push(match diff_line {
+            DiffLine::Same(old_index, new_index) => {
+                DiffLine::Same(old_index + prefix_length, new_index + prefix_length)
+            }
+            DiffLine::Removed(old_index) => DiffLine::Removed(old_index + prefix_length),
+            DiffLine::Added(new_index) => DiffLine::Added(new_index + prefix_length),
+        });
+    }
+    diff_lines.extend((0..suffix_length).map(|index| {
+        DiffLine::Same(
+            old_lines.len() - suffix_length + index,
+            new_lines.len() - suffix_length + index,
+        )
+    }));
+    diff_lines
+}
+
+#[allow(clippy::cast_sign_loss)]
+#[doc(hidden)]
+fn myers_diff(old_lines: &[&str], new_lines: &[&str]) -> Vec<DiffLine> {
+    let old_length = old_lines.len() as isize;
+    let new_length = new_lines.len() as isize;
+    let max_distance = old_length + new_length;
+    let offset = max_distance as usize;
+    let mut furthest = vec![0_isize; 2 * offset + 2];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    'search: for distance in 0..=max_distance {
+        trace.push(furthest.clone());
+        for diagonal in (-distance..=distance).step_by(2) {
+            let index = (diagonal + offset as isize) as usize;
+            let mut old_index = if diagonal == -distance
+                || (diagonal != distance && furthest[index - 1] < furthest[index + 1])
+            {
+                furthest[index + 1]
+            } else {
+                furthest[index - 1] + 1
+            };
+            let mut new_index = old_index - diagonal;
+            while old_index < old_length
+                && new_index < new_length
+                && is_same_line(old_lines, new_lines, old_index as usize, new_index as usize)
+            {
+                old_index += 1;
+                new_index += 1;
+            }
+            furthest[index] = old_index;
+            if old_index >= old_length && new_index >= new_length {
+                break 'search;
+            }
+        }
+    }
+
+    let mut diff_lines = Vec::new();
+    let mut old_index = old_length;
+    let mut new_index = new_length;
+    for (distance, furthest) in trace.iter().enumerate().rev() {
+        let distance = distance as isize;
+        let diagonal = old_index - new_index;
+        let index = (diagonal + offset as isize) as usize;
+        let previous_diagonal = if diagonal == -distance
+            || (diagonal != distance && furthest[index - 1] < furthest[index + 1])
+        {
+            diagonal + 1
+        } else {
+            diagonal - 1
+        };
+        let previous_old_index = furthest[(previous_diagonal + offset as isize) as usize];
+        let previous_new_index = previous_old_index - previous_diagonal;
+        while old_index > previous_old_index && new_index > previous_new_index {
+            old_index -= 1;
+            new_index -= 1;
+            diff_lines.push(DiffLine::Same(old_index as usize, new_index as usize));
+        }
+        if distance > 0 {
+            if old_index == previous_old_index {
+                new_index -= 1;
+                diff_lines.push(DiffLine::Added(new_index as usize));
+            } else {
+                old_index -= 1;
+                diff_lines.push(DiffLine::Removed(old_index as usize));
+            }
+        }
+    }
+    diff_lines.reverse();
+    diff_lines
+}
+
+#[doc(hidden)]
+fn is_same_line(
+    old_lines: &[&str],
+    new_lines: &[&str],
+    old_index: usize,
+    new_index: usize,
+) -> bool {
+    old_lines[old_index] == new_lines[new_index]
+}
@@ -0,0 +1,155 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// The place in a coverage file an error was found at: the coverage file, the line in it (when it is
+// known), and the element (e.g. an XML tag or an LCOV record) which could not be used.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct CoverageEntry {
+    pub file_name: String,
+    pub line_number: Option<usize>,
+    pub element: String,
+}
+
+// Problems which prevent checking (some of) the coverage annotations. These are not the fault of the
+// annotations, so they are reported separately and cause a distinct exit status; the other files
+// are still checked.
+#[doc(hidden)]
+#[derive(Debug)]
+pub enum Error {
+    Read {
+        file_name: String,
+        error: io::Error,
+    },
+    Write {
+        file_name: String,
+        error: io::Error,
+    },
+    NonUtf8Path {
+        path: PathBuf,
+    },
+    InvalidFile {
+        file_name: String,
+        reason: String,
+    },
+    InvalidCoverageEntry {
+        entry: CoverageEntry,
+        reason: String,
+    },
+    UnscannedSourceFile {
+        file_name: String,
+    },
+}
+
+impl Error {
+    #[doc(hidden)]
+    pub fn file_name(&self) -> String {
+        match self {
+            Self::Read { file_name, .. }
+            | Self::Write { file_name, .. }
+            | Self::InvalidFile { file_name, .. }
+            | Self::UnscannedSourceFile { file_name } => file_name.clone(),
+            Self::NonUtf8Path { path } => path.to_string_lossy().to_string(),
+            Self::InvalidCoverageEntry { entry, .. } => entry.file_name.clone(),
+        }
+    }
+
+    #[doc(hidden)]
+    pub const fn line_number(&self) -> Option<usize> {
+        match self {
+            Self::InvalidCoverageEntry { entry, .. } => entry.line_number,
+            _ => None,
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn message(&self) -> String {
+        match self {
+            Self::Read { error, .. } => format!("can't read the file: {error}"),
+            Self::Write { error, .. } => format!("can't write the file: {error}"),
+            Self::NonUtf8Path { .. } => "the path is not valid UTF-8".to_string(),
+            Self::InvalidFile { reason, .. } => reason.clone(),
+            Self::InvalidCoverageEntry { entry, reason } => format!("{}: {reason}", entry.element),
+            Self::UnscannedSourceFile { .. } => {
+                "the file is covered, but its coverage annotations were not collected".to_string()
+            }
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::new(
+            DiagnosticKind::ToolError,
+            &self.file_name(),
+            self.line_number(),
+            None,
+            None,
+            None,
+            self.message(),
+        )
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line_number() {
+            Some(line_number) => write!(
+                formatter,
+                "{}:{line_number}: {}",
+                self.file_name(),
+                self.message()
+            ),
+            None => write!(formatter, "{}: {}", self.file_name(), self.message()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read { error, .. } | Self::Write { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn path_str(path: &Path) -> Result<&str, Error> {
+    path.to_str().ok_or_else(|| Error::NonUtf8Path {
+        path: path.to_path_buf(),
+    })
+}
+
+#[doc(hidden)]
+pub fn read_file(path: &Path) -> Result<String, Error> {
+    std::fs::read_to_string(path).map_err(|error| Error::Read {
+        file_name: path.to_string_lossy().to_string(),
+        error,
+    })
+}
+
+#[doc(hidden)]
+pub fn write_file(path: &str, contents: &str) -> Result<(), Error> {
+    std::fs::write(path, contents).map_err(|error| Error::Write {
+        file_name: path.to_string(),
+        error,
+    })
+}
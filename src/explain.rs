@@ -0,0 +1,129 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::config::Config;
+use crate::coverage::collect_coverage_file_annotations;
+use crate::error::read_file;
+use crate::{
+    collect_text_annotations, effective_line_annotation, relative_file_name, FileAnnotations,
+    ERROR_EXIT_STATUS,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Prints the rules which gave a line its annotation, as recorded by `collect_text_annotations`,
+// followed by the hits of the line in each of the coverage files.
+#[doc(hidden)]
+pub fn explain_line(
+    config: &Config,
+    file_name: &str,
+    line_number: usize,
+    coverage_paths: &[PathBuf],
+    coverage_annotations: &BTreeMap<String, HashMap<i32, u64>>,
+    source_annotations: &BTreeMap<String, FileAnnotations>,
+) {
+    let canonical_file_name = fs::canonicalize(file_name)
+        .ok()
+        .and_then(|path| path.to_str().map(str::to_string))
+        .unwrap_or_else(|| {
+            eprintln!("{file_name}: no such source file");
+            std::process::exit(ERROR_EXIT_STATUS);
+        });
+    let policy = config.file_policy(&canonical_file_name);
+    if policy.is_ignored {
+        eprintln!("{file_name}: ignored by the configured overrides");
+        std::process::exit(ERROR_EXIT_STATUS);
+    }
+    let Some(source_file_annotations) = source_annotations.get(&canonical_file_name) else {
+        eprintln!("{file_name}: not a checked source file");
+        std::process::exit(ERROR_EXIT_STATUS);
+    };
+    let text = read_file(Path::new(&canonical_file_name)).unwrap_or_else(|error| {
+        eprintln!("{error}");
+        std::process::exit(ERROR_EXIT_STATUS);
+    });
+    let lines: Vec<&str> = text.lines().collect();
+    if line_number > lines.len() {
+        eprintln!("{file_name}:{line_number}: no such line");
+        std::process::exit(ERROR_EXIT_STATUS);
+    }
+    let annotation = effective_line_annotation(source_file_annotations, line_number - 1).unwrap();
+
+    println!(
+        "{}:{line_number}: {}",
+        relative_file_name(&canonical_file_name),
+        lines[line_number - 1].trim()
+    );
+    let mut lines_rules = Vec::new();
+    collect_text_annotations(
+        &policy,
+        Path::new(&canonical_file_name),
+        &text,
+        &mut Vec::new(),
+        Some(&mut lines_rules),
+    );
+    for (index, rule) in lines_rules[line_number - 1].iter().enumerate() {
+        println!("  {}. {rule}", index + 1);
+    }
+    println!("annotation: {annotation}");
+    print_line_hits(
+        &canonical_file_name,
+        line_number,
+        coverage_paths,
+        coverage_annotations,
+    );
+}
+
+// Each coverage file is collected again on its own, since the collected coverage only holds the
+// sum of the hits of all of them. Any errors in them were already reported when collecting them.
+#[doc(hidden)]
+fn print_line_hits(
+    file_name: &str,
+    line_number: usize,
+    coverage_paths: &[PathBuf],
+    coverage_annotations: &BTreeMap<String, HashMap<i32, u64>>,
+) {
+    let line_hits = |coverage_annotations: &BTreeMap<String, HashMap<i32, u64>>| {
+        coverage_annotations
+            .get(file_name)
+            .and_then(|coverage_file_annotations| {
+                coverage_file_annotations.get(&(line_number as i32))
+            })
+            .copied()
+    };
+    let Some(total_hits) = line_hits(coverage_annotations) else {
+        println!("coverage: not mentioned by any coverage file (not an executable line)");
+        return;
+    };
+    println!("coverage:");
+    for coverage_path in coverage_paths {
+        let mut path_coverage_annotations = BTreeMap::new();
+        collect_coverage_file_annotations(
+            coverage_path,
+            false,
+            &mut path_coverage_annotations,
+            &mut Vec::new(),
+        );
+        if let Some(hits) = line_hits(&path_coverage_annotations) {
+            println!(
+                "  {}: {hits} hit(s)",
+                relative_file_name(&coverage_path.to_string_lossy())
+            );
+        }
+    }
+    println!("  total: {total_hits} hit(s)");
+}
@@ -0,0 +1,609 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::config::FilePolicy;
+use crate::{
+    collect_text_annotations, is_obsolete_line_mark, is_uncovered_file_annotated, line_mark,
+    FileAnnotations, LineAnnotation, LineMark,
+};
+use std::collections::HashMap;
+use std::path::Path;
+
+#[doc(hidden)]
+struct SourceLine {
+    text: String,
+    terminator: String,
+}
+
+#[doc(hidden)]
+pub fn fix_file_annotations(
+    policy: &FilePolicy,
+    path: &Path,
+    text: &str,
+    coverage_file_annotations: Option<&HashMap<i32, u64>>,
+    source_file_annotations: &FileAnnotations,
+    region_lines: Option<usize>,
+) -> Option<String> {
+    let mut lines = split_lines(text);
+    let is_block_style = is_block_style(&lines);
+    let mut did_fix = false;
+    match (source_file_annotations, coverage_file_annotations) {
+        (FileAnnotations::NotTested, Some(coverage_file_annotations)) => {
+            for line in &mut lines {
+                if line_mark(&line.text) == LineMark::FileNotTested {
+                    did_fix |= remove_marker(&mut line.text, "FILE NOT TESTED");
+                }
+            }
+            if did_fix {
+                let text = join_lines(&lines);
+                if let FileAnnotations::LineAnnotations(line_annotations) =
+                    collect_text_annotations(policy, path, &text, &mut Vec::new(), None)
+                {
+                    fix_lines_annotations(
+                        is_block_style,
+                        &mut lines,
+                        &line_annotations,
+                        coverage_file_annotations,
+                        policy.default_annotation,
+                        region_lines,
+                    );
+                }
+            }
+        }
+
+        (FileAnnotations::LineAnnotations(_), None)
+            if !is_uncovered_file_annotated(policy, source_file_annotations) =>
+        {
+            for line in &mut lines {
+                if let Some(name) = line_marker_name(line_mark(&line.text)) {
+                    remove_marker(&mut line.text, name);
+                }
+            }
+            let terminator = lines
+                .first()
+                .map_or("\n", |line| match line.terminator.as_str() {
+                    "" => "\n",
+                    terminator => terminator,
+                })
+                .to_string();
+            lines.insert(
+                0,
+                SourceLine {
+                    text: marker_comment(is_block_style, "FILE NOT TESTED"),
+                    terminator,
+                },
+            );
+            did_fix = true;
+        }
+
+        (FileAnnotations::LineAnnotations(line_annotations), Some(coverage_file_annotations)) => {
+            did_fix = fix_lines_annotations(
+                is_block_style,
+                &mut lines,
+                line_annotations,
+                coverage_file_annotations,
+                policy.default_annotation,
+                region_lines,
+            );
+        }
+
+        _ => {}
+    }
+    if !did_fix {
+        return None;
+    }
+    Some(join_lines(&lines))
+}
+
+#[doc(hidden)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum LineKind {
+    Uncovered,
+    Ignored,
+    Breaking,
+}
+
+#[doc(hidden)]
+fn fix_lines_annotations(
+    is_block_style: bool,
+    lines: &mut [SourceLine],
+    line_annotations: &[LineAnnotation],
+    coverage_file_annotations: &HashMap<i32, u64>,
+    default_annotation: LineAnnotation,
+    region_lines: Option<usize>,
+) -> bool {
+    let region_annotations = collect_region_annotations(lines);
+    let coverage_line_annotations: Vec<Option<bool>> = (0..lines.len())
+        .map(|line_index| {
+            coverage_file_annotations
+                .get(&(line_index as i32 + 1))
+                .map(|hits| *hits > 0)
+        })
+        .collect();
+    let mut texts: Vec<String> = lines.iter().map(|line| line.text.clone()).collect();
+    let mut start = 0;
+    while start < lines.len() {
+        let region_annotation = region_annotations[start];
+        let mut end = start + 1;
+        while end < lines.len()
+            && region_annotations[end] == region_annotation
+            && Some(line_mark(&lines[end - 1].text)) != region_end_mark(region_annotation)
+        {
+            end += 1;
+        }
+        match region_annotation {
+            LineAnnotation::Tested(_) => fix_outside_lines_annotations(
+                is_block_style,
+                &mut texts[start..end],
+                &line_annotations[start..end],
+                &coverage_line_annotations[start..end],
+                default_annotation,
+                region_lines,
+            ),
+            LineAnnotation::NotTested(_) => fix_not_tested_region_annotations(
+                is_block_style,
+                &mut texts[start..end],
+                &line_annotations[start..end],
+                &coverage_line_annotations[start..end],
+                default_annotation,
+                region_lines,
+            ),
+            _ => {
+                for line_index in start..end {
+                    fix_line_annotation(
+                        is_block_style,
+                        &mut texts[line_index],
+                        line_annotations[line_index],
+                        region_annotation,
+                        coverage_line_annotations[line_index],
+                    );
+                }
+            }
+        }
+        start = end;
+    }
+    let mut did_fix = false;
+    for (line, text) in lines.iter_mut().zip(texts) {
+        if line.text != text {
+            line.text = text;
+            did_fix = true;
+        }
+    }
+    did_fix
+}
+
+// When collapsing runs into regions, a run starts and ends with an uncovered line, and may contain
+// non-executable or untrusted lines, but not covered lines or lines with any marker. Regions are
+// only needed where lines default to TESTED.
+#[doc(hidden)]
+fn fix_outside_lines_annotations(
+    is_block_style: bool,
+    texts: &mut [String],
+    line_annotations: &[LineAnnotation],
+    coverage_line_annotations: &[Option<bool>],
+    default_annotation: LineAnnotation,
+    region_lines: Option<usize>,
+) {
+    let mut is_in_new_region = vec![false; texts.len()];
+    let region_lines = region_lines.filter(|_| default_annotation == LineAnnotation::Tested(false));
+    if let Some(region_lines) = region_lines {
+        let line_kinds: Vec<LineKind> = (0..texts.len())
+            .map(|line_index| {
+                outside_line_kind(
+                    &texts[line_index],
+                    line_annotations[line_index],
+                    coverage_line_annotations[line_index],
+                )
+            })
+            .collect();
+        let mut line_index = 0;
+        while line_index < texts.len() {
+            if line_kinds[line_index] != LineKind::Uncovered {
+                line_index += 1;
+                continue;
+            }
+            let first_line_index = line_index;
+            let mut last_line_index = line_index;
+            let mut uncovered_lines_count = 1;
+            line_index += 1;
+            while line_index < texts.len() && line_kinds[line_index] != LineKind::Breaking {
+                if line_kinds[line_index] == LineKind::Uncovered {
+                    last_line_index = line_index;
+                    uncovered_lines_count += 1;
+                }
+                line_index += 1;
+            }
+            if uncovered_lines_count >= region_lines.max(2) {
+                append_marker(
+                    &mut texts[first_line_index],
+                    is_block_style,
+                    "BEGIN NOT TESTED",
+                );
+                append_marker(
+                    &mut texts[last_line_index],
+                    is_block_style,
+                    "END NOT TESTED",
+                );
+                is_in_new_region[first_line_index..=last_line_index].fill(true);
+            }
+            line_index = last_line_index + 1;
+        }
+    }
+    for (line_index, text) in texts.iter_mut().enumerate() {
+        if !is_in_new_region[line_index] {
+            fix_line_annotation(
+                is_block_style,
+                text,
+                line_annotations[line_index],
+                default_annotation,
+                coverage_line_annotations[line_index],
+            );
+        }
+    }
+}
+
+#[doc(hidden)]
+fn outside_line_kind(
+    text: &str,
+    line_annotation: LineAnnotation,
+    coverage_line_annotation: Option<bool>,
+) -> LineKind {
+    if has_marker(text) {
+        return LineKind::Breaking;
+    }
+    match (line_annotation, coverage_line_annotation) {
+        (LineAnnotation::Tested(false) | LineAnnotation::NotTested(false), Some(false)) => {
+            LineKind::Uncovered
+        }
+        (LineAnnotation::MaybeTested(false), _) | (_, None) => LineKind::Ignored,
+        _ => LineKind::Breaking,
+    }
+}
+
+// Covered lines in a NOT TESTED region can be fixed either by adding a TESTED annotation to each of
+// them, or by removing the region and laying out its lines from scratch. When collapsing runs into
+// regions, whichever modifies fewer lines wins. Otherwise, the region is only removed if adding
+// TESTED annotations is impossible, that is, if a line containing the BEGIN or END marker is
+// covered, and its uncovered lines are then annotated one by one.
+#[doc(hidden)]
+fn fix_not_tested_region_annotations(
+    is_block_style: bool,
+    texts: &mut [String],
+    line_annotations: &[LineAnnotation],
+    coverage_line_annotations: &[Option<bool>],
+    default_annotation: LineAnnotation,
+    region_lines: Option<usize>,
+) {
+    let mut override_texts = texts.to_vec();
+    for (line_index, text) in override_texts.iter_mut().enumerate() {
+        fix_line_annotation(
+            is_block_style,
+            text,
+            line_annotations[line_index],
+            LineAnnotation::NotTested(false),
+            coverage_line_annotations[line_index],
+        );
+    }
+    let is_override_complete = (0..texts.len()).all(|line_index| {
+        line_annotations[line_index] != LineAnnotation::NotTested(false)
+            || coverage_line_annotations[line_index] != Some(true)
+            || !has_marker(&texts[line_index])
+    });
+
+    let last_line_index = texts.len() - 1;
+    let is_simple_region = line_mark(&texts[0]) == LineMark::BeginNotTested
+        && line_mark(&texts[last_line_index]) == LineMark::EndNotTested
+        && texts[1..last_line_index]
+            .iter()
+            .all(|text| !is_region_mark(line_mark(text)));
+    let layout_texts = (is_simple_region && (region_lines.is_some() || !is_override_complete))
+        .then(|| {
+            let mut layout_texts = texts.to_vec();
+            remove_marker(&mut layout_texts[0], "BEGIN NOT TESTED");
+            remove_marker(&mut layout_texts[last_line_index], "END NOT TESTED");
+            let outside_line_annotations: Vec<LineAnnotation> = line_annotations
+                .iter()
+                .zip(texts.iter())
+                .map(|(line_annotation, text)| match line_annotation {
+                    LineAnnotation::NotTested(false) if !text.contains("unreachable!()") => {
+                        default_annotation
+                    }
+                    _ => *line_annotation,
+                })
+                .collect();
+            fix_outside_lines_annotations(
+                is_block_style,
+                &mut layout_texts,
+                &outside_line_annotations,
+                coverage_line_annotations,
+                default_annotation,
+                region_lines,
+            );
+            layout_texts
+        });
+
+    let fixed_texts = match layout_texts {
+        Some(layout_texts)
+            if !is_override_complete
+                || changed_lines_count(texts, &layout_texts)
+                    < changed_lines_count(texts, &override_texts) =>
+        {
+            layout_texts
+        }
+        _ => override_texts,
+    };
+    texts.clone_from_slice(&fixed_texts);
+}
+
+#[doc(hidden)]
+fn changed_lines_count(texts: &[String], fixed_texts: &[String]) -> usize {
+    texts
+        .iter()
+        .zip(fixed_texts)
+        .filter(|(text, fixed_text)| text != fixed_text)
+        .count()
+}
+
+#[doc(hidden)]
+const fn is_region_mark(line_mark: LineMark) -> bool {
+    matches!(
+        line_mark,
+        LineMark::BeginMaybeTested
+            | LineMark::BeginNotTested
+            | LineMark::BeginFlakyTested
+            | LineMark::EndMaybeTested
+            | LineMark::EndNotTested
+            | LineMark::EndFlakyTested
+    )
+}
+
+#[doc(hidden)]
+const fn region_end_mark(region_annotation: LineAnnotation) -> Option<LineMark> {
+    match region_annotation {
+        LineAnnotation::Tested(_) => None,
+        LineAnnotation::MaybeTested(_) => Some(LineMark::EndMaybeTested),
+        LineAnnotation::NotTested(_) => Some(LineMark::EndNotTested),
+        LineAnnotation::FlakyTested(_) => Some(LineMark::EndFlakyTested),
+    }
+}
+
+#[doc(hidden)]
+fn join_lines(lines: &[SourceLine]) -> String {
+    let mut text = String::new();
+    for line in lines {
+        text.push_str(&line.text);
+        text.push_str(&line.terminator);
+    }
+    text
+}
+
+#[doc(hidden)]
+fn fix_line_annotation(
+    is_block_style: bool,
+    text: &mut String,
+    line_annotation: LineAnnotation,
+    region_annotation: LineAnnotation,
+    coverage_line_annotation: Option<bool>,
+) -> bool {
+    let is_in_not_tested_region = region_annotation == LineAnnotation::NotTested(false);
+    let is_in_region = region_annotation != LineAnnotation::Tested(false);
+    match (line_annotation, coverage_line_annotation) {
+        (LineAnnotation::Tested(false), Some(false)) if !has_marker(text) => {
+            append_marker(text, is_block_style, "NOT TESTED");
+            true
+        }
+
+        (LineAnnotation::Tested(true), Some(false)) => {
+            if is_in_not_tested_region {
+                remove_marker(text, "TESTED")
+            } else {
+                replace_marker(text, "TESTED", "NOT TESTED")
+            }
+        }
+
+        (LineAnnotation::NotTested(true), Some(true)) => {
+            if is_in_not_tested_region {
+                replace_marker(text, "NOT TESTED", "TESTED")
+            } else {
+                remove_marker(text, "NOT TESTED")
+            }
+        }
+
+        (LineAnnotation::NotTested(false), Some(true)) if !has_marker(text) => {
+            append_marker(text, is_block_style, "TESTED");
+            true
+        }
+
+        (LineAnnotation::Tested(true), Some(true)) if !is_in_region => {
+            remove_marker(text, "TESTED")
+        }
+        (LineAnnotation::NotTested(true), Some(false)) if is_in_not_tested_region => {
+            remove_marker(text, "NOT TESTED")
+        }
+
+        (LineAnnotation::Tested(true), None) => remove_marker(text, "TESTED"),
+        (LineAnnotation::NotTested(true), None) => remove_marker(text, "NOT TESTED"),
+        (LineAnnotation::MaybeTested(true), None) => remove_marker(text, "MAYBE TESTED"),
+
+        _ => false,
+    }
+}
+
+// This only tracks the properly nested regions, the same way `collect_text_annotations` does.
+#[doc(hidden)]
+fn collect_region_annotations(lines: &[SourceLine]) -> Vec<LineAnnotation> {
+    let mut region_annotation = LineAnnotation::Tested(false);
+    let mut region_annotations = Vec::new();
+    for line in lines {
+        region_annotation = match (line_mark(&line.text), region_annotation) {
+            (LineMark::BeginNotTested, LineAnnotation::Tested(_)) => {
+                LineAnnotation::NotTested(false)
+            }
+            (LineMark::BeginMaybeTested, LineAnnotation::Tested(_)) => {
+                LineAnnotation::MaybeTested(false)
+            }
+            (LineMark::BeginFlakyTested, LineAnnotation::Tested(_)) => {
+                LineAnnotation::FlakyTested(false)
+            }
+            _ => region_annotation,
+        };
+        region_annotations.push(region_annotation);
+        region_annotation = match (line_mark(&line.text), region_annotation) {
+            (LineMark::EndNotTested, LineAnnotation::NotTested(_))
+            | (LineMark::EndMaybeTested, LineAnnotation::MaybeTested(_))
+            | (LineMark::EndFlakyTested, LineAnnotation::FlakyTested(_)) => {
+                LineAnnotation::Tested(false)
+            }
+            _ => region_annotation,
+        };
+    }
+    region_annotations
+}
+
+#[doc(hidden)]
+fn split_lines(text: &str) -> Vec<SourceLine> {
+    text.split_inclusive('\n')
+        .map(|line| {
+            let text = line
+                .strip_suffix('\n')
+                .map_or(line, |text| text.strip_suffix('\r').unwrap_or(text));
+            SourceLine {
+                text: text.to_string(),
+                terminator: line[text.len()..].to_string(),
+            }
+        })
+        .collect()
+}
+
+// Use `/* ... */` markers only if this is what the file already uses for its markers.
+#[doc(hidden)]
+fn is_block_style(lines: &[SourceLine]) -> bool {
+    let mut block_markers_count = 0;
+    let mut line_markers_count = 0;
+    for line in lines {
+        if let Some(name) = any_marker_name(line_mark(&line.text)) {
+            if line.text.contains(&format!("/* {name}")) {
+                block_markers_count += 1;
+            } else {
+                line_markers_count += 1;
+            }
+        }
+    }
+    block_markers_count > line_markers_count
+}
+
+#[doc(hidden)]
+fn has_marker(line: &str) -> bool {
+    line_mark(line) != LineMark::None || is_obsolete_line_mark(line)
+}
+
+#[doc(hidden)]
+const fn line_marker_name(line_mark: LineMark) -> Option<&'static str> {
+    match line_mark {
+        LineMark::LineTested
+        | LineMark::LineMaybeTested
+        | LineMark::LineNotTested
+        | LineMark::LineFlakyTested => any_marker_name(line_mark),
+        _ => None,
+    }
+}
+
+#[doc(hidden)]
+pub const fn any_marker_name(line_mark: LineMark) -> Option<&'static str> {
+    match line_mark {
+        LineMark::None => None,
+        LineMark::LineTested => Some("TESTED"),
+        LineMark::LineMaybeTested => Some("MAYBE TESTED"),
+        LineMark::LineNotTested => Some("NOT TESTED"),
+        LineMark::LineFlakyTested => Some("FLAKY TESTED"),
+        LineMark::BeginMaybeTested => Some("BEGIN MAYBE TESTED"),
+        LineMark::BeginNotTested => Some("BEGIN NOT TESTED"),
+        LineMark::BeginFlakyTested => Some("BEGIN FLAKY TESTED"),
+        LineMark::EndMaybeTested => Some("END MAYBE TESTED"),
+        LineMark::EndNotTested => Some("END NOT TESTED"),
+        LineMark::EndFlakyTested => Some("END FLAKY TESTED"),
+        LineMark::FileMaybeTested => Some("FILE MAYBE TESTED"),
+        LineMark::FileNotTested => Some("FILE NOT TESTED"),
+        LineMark::FileFlakyTested => Some("FILE FLAKY TESTED"),
+    }
+}
+
+#[doc(hidden)]
+fn marker_comment(is_block_style: bool, name: &str) -> String {
+    if is_block_style {
+        format!("/* {name} */")
+    } else {
+        format!("// {name}")
+    }
+}
+
+#[doc(hidden)]
+fn append_marker(line: &mut String, is_block_style: bool, name: &str) {
+    if !line.ends_with(char::is_whitespace) {
+        line.push(' ');
+    }
+    line.push_str(&marker_comment(is_block_style, name));
+}
+
+// Returns the byte range of the marker name itself, and of the whole comment containing it.
+#[doc(hidden)]
+pub fn find_marker(line: &str, name: &str) -> Option<((usize, usize), (usize, usize))> {
+    if let Some(start) = line.find(&format!("// {name}")) {
+        let name_start = start + 3;
+        return Some(((name_start, name_start + name.len()), (start, line.len())));
+    }
+    let start = line.find(&format!("/* {name}"))?;
+    let name_start = start + 3;
+    let end = line[name_start..]
+        .find("*/")
+        .map_or(line.len(), |end| name_start + end + 2);
+    Some(((name_start, name_start + name.len()), (start, end)))
+}
+
+// Only the marker name (and a `:` following it) is removed, unless nothing else remains in the
+// comment, in which case the whole comment is removed. A line which only contained the marker
+// comment becomes empty rather than being removed, so the line numbers still match the coverage
+// files.
+#[doc(hidden)]
+fn remove_marker(line: &mut String, name: &str) -> bool {
+    let Some(((name_start, name_end), (start, end))) = find_marker(line, name) else {
+        return false;
+    };
+    let comment_end = if line[start..].starts_with("/*") && line[..end].ends_with("*/") {
+        end - 2
+    } else {
+        end
+    };
+    let rest = &line[name_end..comment_end];
+    let rest = rest.strip_prefix(':').unwrap_or(rest);
+    if rest.trim().is_empty() {
+        let start = line[..start].trim_end().len();
+        line.replace_range(start..end, "");
+    } else {
+        let rest_start = comment_end - rest.trim_start().len();
+        line.replace_range(name_start..rest_start, "");
+    }
+    true
+}
+
+#[doc(hidden)]
+fn replace_marker(line: &mut String, name: &str, replacement: &str) -> bool {
+    let Some(((start, end), _)) = find_marker(line, name) else {
+        return false;
+    };
+    line.replace_range(start..end, replacement);
+    true
+}
@@ -0,0 +1,146 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+// The (canonical) names of the files which differ between the git reference and the working tree,
+// with the numbers of their lines which were added or modified. Untracked files count as entirely
+// added. A file whose changes only removed lines has an empty set of lines. The error is the reason
+// the changed lines can't be known.
+#[doc(hidden)]
+pub fn changed_lines(git_ref: &str) -> Result<BTreeMap<String, BTreeSet<usize>>, String> {
+    let top_level = git_output(&["rev-parse", "--show-toplevel"])?;
+    let top_level = fs::canonicalize(top_level.trim_end())
+        .map_err(|error| format!("{}: {error}", top_level.trim_end()))?;
+    // The prefixes are explicit, since they may be changed by `diff.noprefix` or
+    // `diff.mnemonicPrefix` in the user's git configuration.
+    let diff = git_output(&[
+        "diff",
+        "--no-color",
+        "--no-ext-diff",
+        "--no-renames",
+        "--unified=0",
+        "--src-prefix=a/",
+        "--dst-prefix=b/",
+        git_ref,
+        "--",
+    ])?;
+
+    let mut changed_lines: BTreeMap<String, BTreeSet<usize>> = BTreeMap::new();
+    let mut file_lines: Option<&mut BTreeSet<usize>> = None;
+    for line in diff.lines() {
+        if let Some(file_name) = line.strip_prefix("+++ ") {
+            // Deleted files have no lines in the working tree.
+            if file_name == "/dev/null" {
+                file_lines = None;
+                continue;
+            }
+            let file_name = diff_file_name(file_name)
+                .and_then(|file_name| canonical_file_name(&top_level, &file_name))
+                .ok_or_else(|| format!("can't resolve the file of the diff line `{line}`"))?;
+            file_lines = Some(changed_lines.entry(file_name).or_default());
+        } else if let (Some(hunk), Some(lines)) = (line.strip_prefix("@@ "), &mut file_lines) {
+            let (first_line, lines_count) = hunk_new_lines(hunk)
+                .ok_or_else(|| format!("can't parse the diff hunk header `{line}`"))?;
+            lines.extend(first_line..first_line + lines_count);
+        }
+    }
+
+    let untracked = git_output(&["ls-files", "--others", "--exclude-standard", "--full-name"])?;
+    for file_name in untracked.lines() {
+        if let Some(file_name) = canonical_file_name(&top_level, file_name) {
+            let lines_count = fs::read_to_string(&file_name).map_or(0, |text| text.lines().count());
+            changed_lines.insert(file_name, (1..=lines_count).collect());
+        }
+    }
+    Ok(changed_lines)
+}
+
+// The name in a `+++ b/<name>` line, which is followed by a tab if it contains a space, and is
+// quoted (using C-style escapes) if it contains special characters.
+#[doc(hidden)]
+fn diff_file_name(file_name: &str) -> Option<String> {
+    let file_name = file_name.strip_suffix('\t').unwrap_or(file_name);
+    let Some(quoted) = file_name.strip_prefix('"') else {
+        return file_name.strip_prefix("b/").map(str::to_string);
+    };
+    let mut bytes = Vec::new();
+    let mut chars = quoted.strip_suffix('"')?.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(char.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        let byte = match chars.next()? {
+            'a' => 0x07,
+            'b' => 0x08,
+            'f' => 0x0c,
+            'n' => b'\n',
+            'r' => b'\r',
+            't' => b'\t',
+            'v' => 0x0b,
+            digit @ '0'..='7' => {
+                let digits: String = [Some(digit), chars.next(), chars.next()]
+                    .into_iter()
+                    .collect::<Option<String>>()?;
+                u8::from_str_radix(&digits, 8).ok()?
+            }
+            char => u8::try_from(char).ok()?,
+        };
+        bytes.push(byte);
+    }
+    String::from_utf8(bytes)
+        .ok()?
+        .strip_prefix("b/")
+        .map(str::to_string)
+}
+
+// A hunk header looks like `@@ -<old>[,<count>] +<new>[,<count>] @@`, where a missing count is 1.
+#[doc(hidden)]
+fn hunk_new_lines(hunk: &str) -> Option<(usize, usize)> {
+    let new_range = hunk.split(' ').find_map(|range| range.strip_prefix('+'))?;
+    let mut parts = new_range.split(',');
+    let first_line = parts.next()?.parse().ok()?;
+    let lines_count = parts.next().map_or(Some(1), |count| count.parse().ok())?;
+    Some((first_line, lines_count))
+}
+
+#[doc(hidden)]
+fn canonical_file_name(top_level: &Path, file_name: &str) -> Option<String> {
+    let path = fs::canonicalize(top_level.join(file_name)).ok()?;
+    Some(path.to_str()?.to_string())
+}
+
+// The `git` commands are run from the current directory, so they apply to the repository
+// containing it.
+#[doc(hidden)]
+fn git_output(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(["-c", "core.quotePath=false"])
+        .args(args)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|error| format!("can't run git: {error}"))?;
+    if !output.status.success() {
+        return Err(format!("`git {}` failed", args.join(" ")));
+    }
+    String::from_utf8(output.stdout)
+        .map_err(|_| format!("`git {}` output is not valid UTF-8", args.join(" ")))
+}
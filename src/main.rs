@@ -26,18 +26,52 @@
 #![allow(clippy::cast_possible_truncation)]
 #![allow(clippy::cast_possible_wrap)]
 
+mod baseline;
+mod cargo;
+mod cli;
+mod config;
+mod coverage;
+mod diagnostic;
+mod diff;
+mod error;
+mod explain;
+mod fix;
+mod git;
+mod report;
+mod summary;
+
+use baseline::{write_baseline, Baseline};
+use cargo::{cargo_workspace, Package};
+use cli::{process_args, Action};
+use config::{load_config_files, Config, FilePolicy};
+use coverage::{collect_coverage_file_annotations, is_coverage_file};
+use diagnostic::{Diagnostic, DiagnosticKind, Severity};
+use diff::unified_diff;
+use error::{path_str, read_file, write_file, Error};
+use explain::explain_line;
+use fix::{any_marker_name, fix_file_annotations};
+use git::changed_lines;
 use regex::Regex;
-use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::{BufRead, BufReader};
+use report::{write_human_diagnostic, write_report, OutputFormat};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use std::vec::Vec;
-use xml::reader::{EventReader, XmlEvent};
+use summary::{collect_summary, summary_json, summary_text, FileSummary, SummaryFormat};
 
 /// The current crate version: 0.4.3
 const VERSION: &str = "0.4.3";
 
 #[doc(hidden)]
+const WRONG_ANNOTATIONS_EXIT_STATUS: i32 = 1;
+
+// Usage, configuration and other errors which prevent checking the annotations.
+#[doc(hidden)]
+const ERROR_EXIT_STATUS: i32 = 2;
+
+#[doc(hidden)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum LineMark {
     None,
     LineTested,
@@ -56,7 +90,7 @@ enum LineMark {
 }
 
 #[doc(hidden)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum LineAnnotation {
     Tested(bool),
     MaybeTested(bool),
@@ -85,64 +119,499 @@ enum FileAnnotations {
 
 #[doc(hidden)]
 fn main() {
-    let flaky_policy = process_args();
-
-    let mut coverage_annotations = HashMap::new();
-    let mut source_annotations = HashMap::new();
-    collect_dir_annotations(
-        flaky_policy,
-        Path::new("."),
+    let mut config = Config::default();
+    load_config_files(&mut config);
+    let action = process_args(&mut config);
+    let mut errors = Vec::new();
+    let packages = collect_packages(&config, &mut errors);
+    let coverage_paths = collect_coverage_paths(&config, &mut errors);
+    let coverage_annotations = collect_coverage_annotations(&config, &coverage_paths, &mut errors);
+    let mut source_annotations = BTreeMap::new();
+    let mut source_diagnostics = Vec::new();
+    collect_packages_source_annotations(
+        &config,
+        &packages,
         &mut source_annotations,
-        &mut coverage_annotations,
+        &mut source_diagnostics,
+        &mut errors,
+    );
+    if let Action::Explain(file_name, line_number) = &action {
+        report_tool_errors(&config, true, &errors);
+        explain_line(
+            &config,
+            file_name,
+            *line_number,
+            &coverage_paths,
+            &coverage_annotations,
+            &source_annotations,
+        );
+        std::process::exit(tool_exit_status(&errors));
+    }
+    if config.fix {
+        fix_packages_annotations(
+            &config,
+            &packages,
+            &coverage_annotations,
+            &mut source_annotations,
+            &mut source_diagnostics,
+            &mut errors,
+        );
+    }
+    let changed_lines = collect_changed_lines(&config);
+    let packages_diagnostics = collect_packages_diagnostics(
+        &config,
+        &packages,
+        &coverage_annotations,
+        &source_annotations,
+        &source_diagnostics,
+        changed_lines.as_ref(),
+        &mut errors,
+    );
+    let is_stats = action == Action::Stats;
+    let mut diagnostics = report_tool_errors(
+        &config,
+        is_stats || config.write_baseline.is_some(),
+        &errors,
+    );
+    if let Some(baseline_path) = &config.write_baseline {
+        let findings = packages_diagnostics.concat();
+        write_baseline(baseline_path, &findings).unwrap_or_else(|error| {
+            eprintln!("{error}");
+            std::process::exit(ERROR_EXIT_STATUS);
+        });
+        eprintln!(
+            "{baseline_path}: wrote {} finding(s) to the baseline",
+            findings.len()
+        );
+        std::process::exit(tool_exit_status(&errors));
+    }
+    let exit_status = report_packages_diagnostics(
+        &config,
+        is_stats,
+        &packages,
+        changed_lines.as_ref(),
+        packages_diagnostics,
+        &mut diagnostics,
+    );
+    let summaries = (config.summary.is_some() || config.format == OutputFormat::Markdown)
+        .then(|| collect_summary(&coverage_annotations, &source_annotations, &diagnostics));
+    if let Some(summaries) = &summaries {
+        print_summary(&config, is_stats, summaries);
+    }
+    if is_stats {
+        std::process::exit(tool_exit_status(&errors));
+    }
+    write_report(
+        &config,
+        &coverage_annotations,
+        &source_annotations,
+        &diagnostics,
+        summaries.as_deref(),
+    );
+    if errors.is_empty() {
+        std::process::exit(exit_status);
+    }
+    std::process::exit(ERROR_EXIT_STATUS);
+}
+
+#[doc(hidden)]
+fn print_summary(config: &Config, is_stats: bool, summaries: &[FileSummary]) {
+    match config.summary {
+        Some(SummaryFormat::Text) if is_stats => print!("{}", summary_text(summaries)),
+        Some(SummaryFormat::Text) => eprint!("{}", summary_text(summaries)),
+        Some(SummaryFormat::Json) if config.format.is_human() || is_stats => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&summary_json(summaries)).unwrap()
+            );
+        }
+        _ => {}
+    }
+}
+
+// The tool errors are reported before the findings, which may be incomplete because of them. They are
+// printed when the findings are printed in a human readable format, or when no report is written.
+#[doc(hidden)]
+fn report_tool_errors(config: &Config, is_printed: bool, errors: &[Error]) -> Vec<Diagnostic> {
+    let diagnostics: Vec<Diagnostic> = errors.iter().map(Error::to_diagnostic).collect();
+    if is_printed || config.format.is_human() {
+        let format = if config.format.is_human() {
+            config.format
+        } else {
+            OutputFormat::Human
+        };
+        for diagnostic in &diagnostics {
+            write_human_diagnostic(format, config.color.is_colored(), diagnostic);
+        }
+    }
+    diagnostics
+}
+
+#[doc(hidden)]
+const fn tool_exit_status(errors: &[Error]) -> i32 {
+    if errors.is_empty() {
+        0
+    } else {
+        ERROR_EXIT_STATUS
+    }
+}
+
+// Reports the (human readable) diagnostics of each package, collects them all into the diagnostics,
+// and returns the exit status. The statistics command only needs the diagnostics for counting the
+// findings. Baseline entries are only reported as fixed if they are in the checked scope, that is,
+// in the checked packages and in the changed lines.
+#[doc(hidden)]
+fn report_packages_diagnostics(
+    config: &Config,
+    is_stats: bool,
+    packages: &[Package],
+    changed_lines: Option<&BTreeMap<String, BTreeSet<usize>>>,
+    mut packages_diagnostics: Vec<Vec<Diagnostic>>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> i32 {
+    let mut baseline = config.baseline.as_deref().map(|path| {
+        Baseline::read(path).unwrap_or_else(|error| {
+            eprintln!("{error}");
+            std::process::exit(ERROR_EXIT_STATUS);
+        })
+    });
+    let is_colored = config.color.is_colored();
+    let mut exit_status = 0;
+    for (package, package_diagnostics) in packages.iter().zip(packages_diagnostics.iter_mut()) {
+        if let Some(baseline) = &mut baseline {
+            baseline.filter(package_diagnostics);
+        }
+        let wrong_files_count = wrong_files_count(package_diagnostics);
+        if config.format.is_human() && !is_stats {
+            for diagnostic in package_diagnostics.iter() {
+                write_human_diagnostic(config.format, is_colored, diagnostic);
+            }
+            if packages.len() > 1 {
+                if wrong_files_count > 0 {
+                    eprintln!(
+                        "{}: wrong coverage annotations in {} file(s)",
+                        package.name, wrong_files_count
+                    );
+                } else {
+                    eprintln!("{}: correct coverage annotations", package.name);
+                }
+            }
+        }
+        if wrong_files_count > 0 {
+            exit_status = WRONG_ANNOTATIONS_EXIT_STATUS;
+        }
+        diagnostics.append(package_diagnostics);
+    }
+    if let Some(baseline) = &baseline {
+        if !is_stats {
+            baseline.report_fixed_entries(|file_name, line_number| {
+                packages
+                    .iter()
+                    .any(|package| is_in_source_roots(&package.source_roots, file_name))
+                    && is_in_changed_lines(changed_lines, file_name, line_number)
+            });
+        }
+    }
+    exit_status
+}
+
+#[doc(hidden)]
+fn collect_packages(config: &Config, errors: &mut Vec<Error>) -> Vec<Package> {
+    if !config.sources.is_empty() {
+        if !config.packages.is_empty() || config.workspace {
+            eprintln!("explicit source roots can't be combined with --package or --workspace");
+            std::process::exit(ERROR_EXIT_STATUS);
+        }
+        let source_roots = config
+            .sources
+            .iter()
+            .map(|source| {
+                let canonical = fs::canonicalize(source).unwrap_or_else(|_| {
+                    eprintln!("{source}: no such source file or directory");
+                    std::process::exit(ERROR_EXIT_STATUS);
+                });
+                source_root_name(&canonical)
+            })
+            .collect();
+        return vec![Package {
+            name: String::new(),
+            source_roots,
+        }];
+    }
+    let Some(workspace) = cargo_workspace(errors) else {
+        if !config.packages.is_empty() || config.workspace {
+            eprintln!("--package and --workspace require a cargo workspace");
+            std::process::exit(ERROR_EXIT_STATUS);
+        }
+        let source_roots = ["src", "tests"]
+            .iter()
+            .filter_map(|dir| fs::canonicalize(dir).ok())
+            .map(|canonical| source_root_name(&canonical))
+            .collect();
+        return vec![Package {
+            name: String::new(),
+            source_roots,
+        }];
+    };
+    for package_name in &config.packages {
+        if !workspace
+            .members
+            .iter()
+            .any(|package| &package.name == package_name)
+        {
+            eprintln!("{package_name}: no such package in the workspace");
+            std::process::exit(ERROR_EXIT_STATUS);
+        }
+    }
+    workspace
+        .members
+        .into_iter()
+        .filter(|package| {
+            if config.packages.is_empty() {
+                config.workspace || workspace.default_members.contains(&package.name)
+            } else {
+                config.packages.contains(&package.name)
+            }
+        })
+        .collect()
+}
+
+// The source roots are the prefixes of the source file names, so they must be valid UTF-8.
+#[doc(hidden)]
+fn source_root_name(canonical: &Path) -> String {
+    path_str(canonical).map_or_else(
+        |error| {
+            eprintln!("{error}");
+            std::process::exit(ERROR_EXIT_STATUS);
+        },
+        str::to_string,
     )
-    .unwrap();
-    let exit_status =
-        report_wrong_annotations(flaky_policy, &coverage_annotations, &source_annotations);
-    std::process::exit(exit_status);
 }
 
 #[doc(hidden)]
-fn collect_dir_annotations(
-    flaky_policy: FlakyPolicy,
+fn collect_packages_source_annotations(
+    config: &Config,
+    packages: &[Package],
+    source_annotations: &mut BTreeMap<String, FileAnnotations>,
+    source_diagnostics: &mut Vec<Diagnostic>,
+    errors: &mut Vec<Error>,
+) {
+    for source_root in packages.iter().flat_map(|package| &package.source_roots) {
+        let path = Path::new(source_root);
+        if path.is_dir() {
+            collect_dir_source_annotations(
+                config,
+                path,
+                source_annotations,
+                source_diagnostics,
+                errors,
+            );
+        } else {
+            let policy = config.file_policy(source_root);
+            if !policy.is_ignored {
+                match collect_file_annotations(&policy, path, source_diagnostics) {
+                    Ok(annotations) => {
+                        source_annotations.insert(source_root.clone(), annotations);
+                    }
+                    Err(error) => errors.push(error),
+                }
+            }
+        }
+    }
+}
+
+#[doc(hidden)]
+fn is_in_source_roots(source_roots: &[String], file_name: &str) -> bool {
+    source_roots.iter().any(|source_root| {
+        file_name
+            .strip_prefix(source_root.as_str())
+            .is_some_and(|suffix| suffix.is_empty() || suffix.starts_with('/'))
+    })
+}
+
+// The coverage files are collected up front, so that the coverage of each of them is available (for
+// explaining a line) and not only the merged coverage of all of them.
+#[doc(hidden)]
+fn collect_coverage_paths(config: &Config, errors: &mut Vec<Error>) -> Vec<PathBuf> {
+    let mut coverage_paths = Vec::new();
+    if config.coverage.is_empty() {
+        collect_dir_coverage_paths(Path::new("."), &mut coverage_paths, errors);
+    } else {
+        for pattern in &config.coverage {
+            collect_pattern_coverage_paths(pattern, &mut coverage_paths, errors);
+        }
+    }
+    coverage_paths
+}
+
+// The paths are either all explicitly configured, or all found by searching the current directory.
+#[doc(hidden)]
+fn collect_coverage_annotations(
+    config: &Config,
+    coverage_paths: &[PathBuf],
+    errors: &mut Vec<Error>,
+) -> BTreeMap<String, HashMap<i32, u64>> {
+    let mut coverage_annotations = BTreeMap::new();
+    for coverage_path in coverage_paths {
+        collect_coverage_file_annotations(
+            coverage_path,
+            !config.coverage.is_empty(),
+            &mut coverage_annotations,
+            errors,
+        );
+    }
+    coverage_annotations
+}
+
+#[doc(hidden)]
+fn collect_pattern_coverage_paths(
+    pattern: &str,
+    coverage_paths: &mut Vec<PathBuf>,
+    errors: &mut Vec<Error>,
+) {
+    let paths = glob::glob(pattern).unwrap_or_else(|error| {
+        eprintln!("{pattern}: invalid coverage file pattern: {error}");
+        std::process::exit(ERROR_EXIT_STATUS);
+    });
+    let mut did_match = false;
+    for path in paths {
+        let path = match path {
+            Ok(path) => path,
+            Err(error) => {
+                errors.push(Error::Read {
+                    file_name: error.path().to_string_lossy().to_string(),
+                    error: error.into(),
+                });
+                continue;
+            }
+        };
+        if !is_coverage_file(&path) {
+            eprintln!(
+                "{}: unknown coverage file format; expected .xml (Cobertura), .info or .lcov (LCOV), or .json (llvm-cov)",
+                path.display()
+            );
+            std::process::exit(ERROR_EXIT_STATUS);
+        }
+        coverage_paths.push(path);
+        did_match = true;
+    }
+    if !did_match {
+        eprintln!("{pattern}: no such coverage file(s)");
+        std::process::exit(ERROR_EXIT_STATUS);
+    }
+}
+
+#[doc(hidden)]
+fn collect_dir_coverage_paths(
+    dir: &Path,
+    coverage_paths: &mut Vec<PathBuf>,
+    errors: &mut Vec<Error>,
+) {
+    for path in dir_entries_paths(dir, errors) {
+        if path.is_dir() {
+            collect_dir_coverage_paths(&path, coverage_paths, errors);
+        } else if let Ok(canonical) = fs::canonicalize(path) {
+            let file_name = canonical.to_string_lossy();
+            if file_name.ends_with("/cobertura.xml")
+                || file_name.ends_with("/lcov.info")
+                || file_name.ends_with(".lcov")
+                || file_name.ends_with("/coverage.json")
+            {
+                coverage_paths.push(canonical);
+            }
+        }
+    }
+}
+
+// Entries which can't be read are reported, and the rest of the directory is still used.
+#[doc(hidden)]
+fn dir_entries_paths(dir: &Path, errors: &mut Vec<Error>) -> Vec<PathBuf> {
+    let read_error = |error| Error::Read {
+        file_name: dir.to_string_lossy().to_string(),
+        error,
+    };
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| {
+                entry
+                    .map(|entry| entry.path())
+                    .map_err(|error| errors.push(read_error(error)))
+                    .ok()
+            })
+            .collect(),
+        Err(error) => {
+            errors.push(read_error(error));
+            Vec::new()
+        }
+    }
+}
+
+#[doc(hidden)]
+fn collect_dir_source_annotations(
+    config: &Config,
     dir: &Path,
-    source_annotations: &mut HashMap<String, FileAnnotations>,
-    coverage_annotations: &mut HashMap<String, HashMap<i32, bool>>,
-) -> std::io::Result<()> {
-    let entries: fs::ReadDir = fs::read_dir(dir)?;
-    for entry in entries {
-        let entry: fs::DirEntry = entry?;
-        let path = entry.path();
+    source_annotations: &mut BTreeMap<String, FileAnnotations>,
+    source_diagnostics: &mut Vec<Diagnostic>,
+    errors: &mut Vec<Error>,
+) {
+    for path in dir_entries_paths(dir, errors) {
         if path.is_dir() {
-            collect_dir_annotations(
-                flaky_policy,
+            collect_dir_source_annotations(
+                config,
                 &path,
                 source_annotations,
-                coverage_annotations,
-            )?;
+                source_diagnostics,
+                errors,
+            );
         } else if let Ok(canonical) = fs::canonicalize(path) {
-            let file_name = canonical.as_path().to_str().unwrap();
-            if file_name.ends_with("/cobertura.xml") {
-                collect_coverage_annotations(canonical.as_path(), coverage_annotations);
-            } else if file_name.ends_with(".rs") {
-                let annotations = collect_file_annotations(flaky_policy, canonical.as_path());
-                source_annotations.insert(file_name.to_string(), annotations);
+            if canonical
+                .extension()
+                .is_none_or(|extension| extension != "rs")
+            {
+                continue;
+            }
+            let annotations = path_str(&canonical).and_then(|file_name| {
+                let policy = config.file_policy(file_name);
+                if policy.is_ignored {
+                    return Ok(None);
+                }
+                collect_file_annotations(&policy, &canonical, source_diagnostics)
+                    .map(|annotations| Some((file_name.to_string(), annotations)))
+            });
+            match annotations {
+                Ok(Some((file_name, annotations))) => {
+                    source_annotations.insert(file_name, annotations);
+                }
+                Ok(None) => {}
+                Err(error) => errors.push(error),
             }
         }
     }
-    Ok(())
 }
 
-#[allow(clippy::too_many_lines)]
+// The path must be valid UTF-8, which is the case for the canonical source file names.
 #[doc(hidden)]
-fn collect_file_annotations(flaky_policy: FlakyPolicy, path: &Path) -> FileAnnotations {
-    let file = File::open(path).unwrap_or_else(|_| panic!("can't open {}", path.to_str().unwrap()));
-    let file = BufReader::new(file);
-    let mut region_annotation = LineAnnotation::Tested(false);
-    let mut is_file_not_tested = false;
-    let mut is_file_maybe_tested = false;
-    let mut is_file_flaky_tested = false;
-    let mut line_annotations = Vec::new();
-    let untrusted_regex = Regex::new(
+fn collect_file_annotations(
+    policy: &FilePolicy,
+    path: &Path,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<FileAnnotations, Error> {
+    let text = read_file(path)?;
+    Ok(collect_text_annotations(
+        policy,
+        path,
+        &text,
+        diagnostics,
+        None,
+    ))
+}
+
+// Lines which are blank, only hold a comment, close a block, start an `else`, or hold an attribute or
+// an `impl` header are sometimes reported as covered and sometimes not, so their coverage can't be
+// trusted.
+#[doc(hidden)]
+fn untrusted_regex() -> Regex {
+    Regex::new(
         r"(?x)
             ^
             \s*
@@ -179,14 +648,70 @@ fn collect_file_annotations(flaky_policy: FlakyPolicy, path: &Path) -> FileAnnot
             $
         ",
     )
-    .unwrap();
-    for (mut line_number, line) in file.lines().enumerate() {
+    .unwrap()
+}
+
+// The name of the annotation of a line.
+#[doc(hidden)]
+fn effective_line_annotation(
+    source_file_annotations: &FileAnnotations,
+    line_index: usize,
+) -> Option<&'static str> {
+    match source_file_annotations {
+        FileAnnotations::NotTested => Some("NOT TESTED"),
+        FileAnnotations::MaybeTested => Some("MAYBE TESTED"),
+        FileAnnotations::LineAnnotations(line_annotations) => line_annotations
+            .get(line_index)
+            .map(|line_annotation| line_annotation_name(*line_annotation)),
+    }
+}
+
+// If `lines_rules` is given, it receives the rules applied to each line (in the order they were
+// applied), for explaining the annotations to the user.
+#[allow(clippy::too_many_lines)]
+#[doc(hidden)]
+fn collect_text_annotations(
+    policy: &FilePolicy,
+    path: &Path,
+    text: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+    lines_rules: Option<&mut Vec<Vec<String>>>,
+) -> FileAnnotations {
+    let file_name = path.to_str().unwrap();
+    let mut region_annotation = LineAnnotation::Tested(false);
+    let mut region_line_number = 0;
+    let mut file_not_tested_line_number = None;
+    let mut file_maybe_tested_line_number = None;
+    let mut file_flaky_tested_line_number = None;
+    let mut line_annotations = Vec::new();
+    let mut collected_lines_rules = Vec::new();
+    let untrusted_regex = untrusted_regex();
+    for (mut line_number, line_text) in text.lines().enumerate() {
         line_number += 1;
-        let line_text = line.unwrap();
-        let line_mark = extract_line_mark(path.to_str().unwrap(), line_number, line_text.as_ref());
+        let mut rules = Vec::new();
+        if region_annotation != LineAnnotation::Tested(false) {
+            rules.push(region_rule(region_annotation, region_line_number));
+        }
+        let line_mark = extract_line_mark(file_name, line_number, line_text, diagnostics);
+        if is_obsolete_line_mark(line_text) {
+            let (obsolete, _) = obsolete_marker_names(line_text);
+            rules.push(format!("the obsolete {obsolete} annotation is ignored"));
+        }
+        verify_allowed_line_mark(
+            policy,
+            file_name,
+            line_number,
+            line_mark,
+            diagnostics,
+            &mut rules,
+        );
+        let is_file_annotated = file_not_tested_line_number.is_some()
+            || file_maybe_tested_line_number.is_some()
+            || file_flaky_tested_line_number.is_some();
         let (line_annotation, next_region_annotation) = match (line_mark, region_annotation) {
             (LineMark::None, region_annotation) => {
                 if line_text.contains("unreachable!()") {
+                    rules.push("contains unreachable!(): NOT TESTED".to_string());
                     (LineAnnotation::NotTested(false), region_annotation)
                 } else {
                     (region_annotation, region_annotation)
@@ -194,98 +719,108 @@ fn collect_file_annotations(flaky_policy: FlakyPolicy, path: &Path) -> FileAnnot
             }
 
             (LineMark::LineTested, LineAnnotation::Tested(_)) => {
-                eprintln!(
-                    "{}:{}: redundant TESTED coverage annotation",
-                    path.to_str().unwrap(),
-                    line_number
-                );
+                rules.push(explicit_rule("TESTED"));
+                // Outside regions, this is only redundant if it is also the default.
+                if policy.default_annotation == LineAnnotation::Tested(false) {
+                    diagnostics.push(redundant_diagnostic(file_name, line_number, "TESTED"));
+                }
                 (LineAnnotation::Tested(true), LineAnnotation::Tested(false))
             }
             (LineMark::LineTested, region_annotation) => {
+                rules.push(explicit_rule("TESTED"));
                 (LineAnnotation::Tested(true), region_annotation)
             }
 
             (LineMark::LineNotTested, LineAnnotation::NotTested(_)) => {
-                eprintln!(
-                    "{}:{}: redundant NOT TESTED coverage annotation",
-                    path.to_str().unwrap(),
-                    line_number
-                );
+                rules.push(explicit_rule("NOT TESTED"));
+                diagnostics.push(redundant_diagnostic(file_name, line_number, "NOT TESTED"));
                 (
                     LineAnnotation::NotTested(true),
                     LineAnnotation::NotTested(false),
                 )
             }
             (LineMark::LineNotTested, region_annotation) => {
+                rules.push(explicit_rule("NOT TESTED"));
                 (LineAnnotation::NotTested(true), region_annotation)
             }
 
             (LineMark::LineMaybeTested, LineAnnotation::MaybeTested(_)) => {
-                eprintln!(
-                    "{}:{}: redundant MAYBE TESTED coverage annotation",
-                    path.to_str().unwrap(),
-                    line_number
-                );
+                rules.push(explicit_rule("MAYBE TESTED"));
+                diagnostics.push(redundant_diagnostic(file_name, line_number, "MAYBE TESTED"));
                 (
                     LineAnnotation::MaybeTested(true),
                     LineAnnotation::MaybeTested(false),
                 )
             }
             (LineMark::LineMaybeTested, region_annotation) => {
+                rules.push(explicit_rule("MAYBE TESTED"));
                 (LineAnnotation::MaybeTested(true), region_annotation)
             }
 
             (LineMark::LineFlakyTested, LineAnnotation::FlakyTested(_)) => {
-                eprintln!(
-                    "{}:{}: redundant FLAKY TESTED coverage annotation",
-                    path.to_str().unwrap(),
-                    line_number
-                );
+                rules.push(explicit_rule("FLAKY TESTED"));
+                diagnostics.push(redundant_diagnostic(file_name, line_number, "FLAKY TESTED"));
                 (
                     LineAnnotation::FlakyTested(true),
                     LineAnnotation::FlakyTested(false),
                 )
             }
             (LineMark::LineFlakyTested, region_annotation) => {
+                rules.push(explicit_rule("FLAKY TESTED"));
                 (LineAnnotation::FlakyTested(true), region_annotation)
             }
 
-            (LineMark::BeginNotTested, LineAnnotation::Tested(_)) => (
-                LineAnnotation::NotTested(false),
-                LineAnnotation::NotTested(false),
-            ),
+            (LineMark::BeginNotTested, LineAnnotation::Tested(_)) => {
+                region_line_number = line_number;
+                rules.push(region_rule(LineAnnotation::NotTested(false), line_number));
+                (
+                    LineAnnotation::NotTested(false),
+                    LineAnnotation::NotTested(false),
+                )
+            }
             (LineMark::BeginNotTested, region_annotation) => {
-                eprintln!(
-                    "{}:{}: ignored nested BEGIN NOT TESTED coverage annotation",
-                    path.to_str().unwrap(),
-                    line_number
-                );
+                rules.push(nested_rule("BEGIN NOT TESTED"));
+                diagnostics.push(nested_diagnostic(
+                    file_name,
+                    line_number,
+                    "BEGIN NOT TESTED",
+                ));
                 (region_annotation, region_annotation)
             }
 
-            (LineMark::BeginMaybeTested, LineAnnotation::Tested(_)) => (
-                LineAnnotation::MaybeTested(false),
-                LineAnnotation::MaybeTested(false),
-            ),
+            (LineMark::BeginMaybeTested, LineAnnotation::Tested(_)) => {
+                region_line_number = line_number;
+                rules.push(region_rule(LineAnnotation::MaybeTested(false), line_number));
+                (
+                    LineAnnotation::MaybeTested(false),
+                    LineAnnotation::MaybeTested(false),
+                )
+            }
             (LineMark::BeginMaybeTested, region_annotation) => {
-                eprintln!(
-                    "{}:{}: ignored nested BEGIN MAYBE TESTED coverage annotation",
-                    path.to_str().unwrap(),
-                    line_number
-                );
+                rules.push(nested_rule("BEGIN MAYBE TESTED"));
+                diagnostics.push(nested_diagnostic(
+                    file_name,
+                    line_number,
+                    "BEGIN MAYBE TESTED",
+                ));
                 (region_annotation, region_annotation)
             }
 
-            (LineMark::BeginFlakyTested, LineAnnotation::Tested(_)) => (
-                LineAnnotation::FlakyTested(false),
-                LineAnnotation::FlakyTested(false),
-            ),
+            (LineMark::BeginFlakyTested, LineAnnotation::Tested(_)) => {
+                region_line_number = line_number;
+                rules.push(region_rule(LineAnnotation::FlakyTested(false), line_number));
+                (
+                    LineAnnotation::FlakyTested(false),
+                    LineAnnotation::FlakyTested(false),
+                )
+            }
             (LineMark::BeginFlakyTested, region_annotation) => {
-                eprintln!(
-                    "{}:{}: ignored nested BEGIN FLAKY TESTED coverage annotation",
-                    path.to_str().unwrap(),
-                    line_number
-                );
+                rules.push(nested_rule("BEGIN FLAKY TESTED"));
+                diagnostics.push(nested_diagnostic(
+                    file_name,
+                    line_number,
+                    "BEGIN FLAKY TESTED",
+                ));
                 (region_annotation, region_annotation)
             }
 
@@ -294,11 +829,8 @@ fn collect_file_annotations(flaky_policy: FlakyPolicy, path: &Path) -> FileAnnot
                 LineAnnotation::Tested(false),
             ),
             (LineMark::EndNotTested, region_annotation) => {
-                eprintln!(
-                    "{}:{}: ignored nested END NOT TESTED coverage annotation",
-                    path.to_str().unwrap(),
-                    line_number
-                );
+                rules.push(nested_rule("END NOT TESTED"));
+                diagnostics.push(nested_diagnostic(file_name, line_number, "END NOT TESTED"));
                 (region_annotation, region_annotation)
             }
 
@@ -307,11 +839,12 @@ fn collect_file_annotations(flaky_policy: FlakyPolicy, path: &Path) -> FileAnnot
                 LineAnnotation::Tested(false),
             ),
             (LineMark::EndMaybeTested, region_annotation) => {
-                eprintln!(
-                    "{}:{}: ignored nested END MAYBE TESTED coverage annotation",
-                    path.to_str().unwrap(),
-                    line_number
-                );
+                rules.push(nested_rule("END MAYBE TESTED"));
+                diagnostics.push(nested_diagnostic(
+                    file_name,
+                    line_number,
+                    "END MAYBE TESTED",
+                ));
                 (region_annotation, region_annotation)
             }
 
@@ -320,86 +853,288 @@ fn collect_file_annotations(flaky_policy: FlakyPolicy, path: &Path) -> FileAnnot
                 LineAnnotation::Tested(false),
             ),
             (LineMark::EndFlakyTested, region_annotation) => {
-                eprintln!(
-                    "{}:{}: ignored nested END FLAKY TESTED coverage annotation",
-                    path.to_str().unwrap(),
-                    line_number
-                );
+                rules.push(nested_rule("END FLAKY TESTED"));
+                diagnostics.push(nested_diagnostic(
+                    file_name,
+                    line_number,
+                    "END FLAKY TESTED",
+                ));
                 (region_annotation, region_annotation)
             }
 
             (LineMark::FileNotTested, region_annotation) => {
-                if is_file_not_tested || is_file_maybe_tested || is_file_flaky_tested {
-                    eprintln!(
-                        "{}:{}: repeated FILE NOT/MAYBE/FLAKY TESTED coverage annotation",
-                        path.to_str().unwrap(),
-                        line_number
-                    );
+                if is_file_annotated {
+                    diagnostics.push(repeated_file_diagnostic(
+                        file_name,
+                        line_number,
+                        "FILE NOT TESTED",
+                    ));
                 }
-                is_file_not_tested = true;
+                file_not_tested_line_number.get_or_insert(line_number);
                 (region_annotation, region_annotation)
             }
 
             (LineMark::FileMaybeTested, region_annotation) => {
-                if is_file_not_tested || is_file_maybe_tested || is_file_flaky_tested {
-                    eprintln!(
-                        "{}:{}: repeated FILE NOT/MAYBE/FLAKY TESTED coverage annotation",
-                        path.to_str().unwrap(),
-                        line_number
-                    );
+                if is_file_annotated {
+                    diagnostics.push(repeated_file_diagnostic(
+                        file_name,
+                        line_number,
+                        "FILE MAYBE TESTED",
+                    ));
                 }
-                is_file_maybe_tested = true;
+                file_maybe_tested_line_number.get_or_insert(line_number);
                 (region_annotation, region_annotation)
             }
 
             (LineMark::FileFlakyTested, region_annotation) => {
-                if is_file_not_tested || is_file_maybe_tested || is_file_flaky_tested {
-                    eprintln!(
-                        "{}:{}: repeated FILE NOT/MAYBE/FLAKY TESTED coverage annotation",
-                        path.to_str().unwrap(),
-                        line_number
-                    );
+                if is_file_annotated {
+                    diagnostics.push(repeated_file_diagnostic(
+                        file_name,
+                        line_number,
+                        "FILE FLAKY TESTED",
+                    ));
                 }
-                is_file_flaky_tested = true;
+                file_flaky_tested_line_number.get_or_insert(line_number);
                 (region_annotation, region_annotation)
             }
         };
-        line_annotations.push(if untrusted_regex.is_match(line_text.as_ref()) {
+        line_annotations.push(if untrusted_regex.is_match(line_text) {
+            rules.push(
+                "untrusted line (blank, comment, closing brace, else, attribute or impl): \
+                 MAYBE TESTED"
+                    .to_string(),
+            );
             LineAnnotation::MaybeTested(false)
         } else {
             line_annotation
         });
+        collected_lines_rules.push(rules);
         region_annotation = next_region_annotation;
     }
-    if is_file_maybe_tested || (is_file_flaky_tested && flaky_policy == FlakyPolicy::MaybeTested) {
-        verify_untested_file_annotations(path, &line_annotations);
-        FileAnnotations::MaybeTested
-    } else if is_file_not_tested || (is_file_flaky_tested && flaky_policy == FlakyPolicy::NotTested)
-    {
-        verify_untested_file_annotations(path, &line_annotations);
-        FileAnnotations::NotTested
+    let is_recording = lines_rules.is_some();
+    for (line_annotation, rules) in line_annotations.iter_mut().zip(&mut collected_lines_rules) {
+        if *line_annotation == LineAnnotation::Tested(false) {
+            *line_annotation = policy.default_annotation;
+            if is_recording {
+                rules.push(default_rule(policy.default_annotation));
+            }
+        }
+    }
+    let flaky_policy = policy.flaky_policy;
+    let flaky_name = flaky_policy.name();
+    let (file_annotations, file_rule): (Option<FileAnnotations>, Option<String>) = match (
+        file_maybe_tested_line_number,
+        file_not_tested_line_number,
+        file_flaky_tested_line_number,
+        flaky_policy,
+    ) {
+        (Some(line_number), _, _, _) => (
+            Some(FileAnnotations::MaybeTested),
+            Some(format!(
+                "FILE MAYBE TESTED annotation at line {line_number}: MAYBE TESTED"
+            )),
+        ),
+        (None, _, Some(line_number), FlakyPolicy::MaybeTested) => (
+            Some(FileAnnotations::MaybeTested),
+            Some(format!(
+                "FILE FLAKY TESTED annotation at line {line_number}, \
+                 with the {flaky_name} flaky policy: MAYBE TESTED"
+            )),
+        ),
+        (None, Some(line_number), _, _) => (
+            Some(FileAnnotations::NotTested),
+            Some(format!(
+                "FILE NOT TESTED annotation at line {line_number}: NOT TESTED"
+            )),
+        ),
+        (None, None, Some(line_number), FlakyPolicy::NotTested) => (
+            Some(FileAnnotations::NotTested),
+            Some(format!(
+                "FILE FLAKY TESTED annotation at line {line_number}, \
+                 with the {flaky_name} flaky policy: NOT TESTED"
+            )),
+        ),
+        (None, None, Some(line_number), _) => (
+            None,
+            Some(format!(
+                "FILE FLAKY TESTED annotation at line {line_number} \
+                 is ignored with the {flaky_name} flaky policy"
+            )),
+        ),
+        (None, None, None, _) => (None, None),
+    };
+    if let Some(lines_rules) = lines_rules {
+        for (line_annotation, rules) in line_annotations.iter().zip(&mut collected_lines_rules) {
+            if let Some(file_rule) = &file_rule {
+                rules.push(file_rule.clone());
+            } else if matches!(line_annotation, LineAnnotation::FlakyTested(_)) {
+                rules.push(format!(
+                    "FLAKY TESTED lines are checked using the {flaky_name} flaky policy"
+                ));
+            }
+        }
+        *lines_rules = collected_lines_rules;
+    }
+    if let Some(file_annotations) = file_annotations {
+        verify_untested_file_annotations(file_name, &line_annotations, diagnostics);
+        file_annotations
     } else {
         FileAnnotations::LineAnnotations(line_annotations)
     }
 }
 
 #[doc(hidden)]
-fn verify_untested_file_annotations(path: &Path, line_annotations: &[LineAnnotation]) {
+fn region_rule(region_annotation: LineAnnotation, line_number: usize) -> String {
+    let name = line_annotation_name(region_annotation);
+    format!("inside the BEGIN {name} region at line {line_number}: {name}")
+}
+
+#[doc(hidden)]
+fn explicit_rule(name: &str) -> String {
+    format!("explicit {name} annotation: {name}")
+}
+
+#[doc(hidden)]
+fn nested_rule(name: &str) -> String {
+    format!("the nested {name} annotation is ignored")
+}
+
+#[doc(hidden)]
+fn default_rule(default_annotation: LineAnnotation) -> String {
+    let name = line_annotation_name(default_annotation);
+    if default_annotation == LineAnnotation::Tested(false) {
+        format!("no other rule applies: the default {name} annotation")
+    } else {
+        format!("no other rule applies: the default {name} annotation of the configured overrides")
+    }
+}
+
+#[doc(hidden)]
+fn redundant_diagnostic(file_name: &str, line_number: usize, name: &'static str) -> Diagnostic {
+    Diagnostic::new(
+        DiagnosticKind::RedundantAnnotation,
+        file_name,
+        Some(line_number),
+        Some(name),
+        None,
+        None,
+        format!("redundant {name} coverage annotation"),
+    )
+}
+
+#[doc(hidden)]
+fn nested_diagnostic(file_name: &str, line_number: usize, name: &'static str) -> Diagnostic {
+    Diagnostic::new(
+        DiagnosticKind::NestedRegion,
+        file_name,
+        Some(line_number),
+        Some(name),
+        None,
+        None,
+        format!("ignored nested {name} coverage annotation"),
+    )
+}
+
+#[doc(hidden)]
+fn repeated_file_diagnostic(file_name: &str, line_number: usize, name: &'static str) -> Diagnostic {
+    Diagnostic::new(
+        DiagnosticKind::RepeatedFileAnnotation,
+        file_name,
+        Some(line_number),
+        Some(name),
+        None,
+        None,
+        "repeated FILE NOT/MAYBE/FLAKY TESTED coverage annotation".to_string(),
+    )
+}
+
+#[doc(hidden)]
+fn verify_allowed_line_mark(
+    policy: &FilePolicy,
+    file_name: &str,
+    line_number: usize,
+    line_mark: LineMark,
+    diagnostics: &mut Vec<Diagnostic>,
+    rules: &mut Vec<String>,
+) {
+    let Some(name) = any_marker_name(line_mark) else {
+        return;
+    };
+    if !policy.is_allowed_marker(name) {
+        rules.push(format!(
+            "the {name} annotation is not in the configured markers (but is still applied)"
+        ));
+        diagnostics.push(Diagnostic::new(
+            DiagnosticKind::DisallowedAnnotation,
+            file_name,
+            Some(line_number),
+            Some(name),
+            None,
+            None,
+            format!("{name} coverage annotation is not allowed"),
+        ));
+    }
+}
+
+#[doc(hidden)]
+fn verify_untested_file_annotations(
+    file_name: &str,
+    line_annotations: &[LineAnnotation],
+    diagnostics: &mut Vec<Diagnostic>,
+) {
     for (mut line_number, line_annotation) in line_annotations.iter().enumerate() {
         line_number += 1;
         if is_explicit(*line_annotation) {
-            eprintln!(
-                "{}:{}: line coverage annotation in a FILE which is NOT/MAYBE/FLAKY TESTED",
-                path.to_str().unwrap(),
-                line_number
-            );
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::AnnotationInUntestedFile,
+                file_name,
+                Some(line_number),
+                Some(line_annotation_name(*line_annotation)),
+                None,
+                None,
+                "line coverage annotation in a FILE which is NOT/MAYBE/FLAKY TESTED".to_string(),
+            ));
         }
     }
 }
 
 #[doc(hidden)]
-fn extract_line_mark(path: &str, line_number: usize, line: &str) -> LineMark {
-    if line.contains("// APPEARS NOT TESTED")
+const fn line_annotation_name(line_annotation: LineAnnotation) -> &'static str {
+    match line_annotation {
+        LineAnnotation::Tested(_) => "TESTED",
+        LineAnnotation::MaybeTested(_) => "MAYBE TESTED",
+        LineAnnotation::NotTested(_) => "NOT TESTED",
+        LineAnnotation::FlakyTested(_) => "FLAKY TESTED",
+    }
+}
+
+#[doc(hidden)]
+fn extract_line_mark(
+    file_name: &str,
+    line_number: usize,
+    line: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> LineMark {
+    if is_obsolete_line_mark(line) {
+        let (actual, expected) = obsolete_marker_names(line);
+        diagnostics.push(Diagnostic::new(
+            DiagnosticKind::ObsoleteAnnotation,
+            file_name,
+            Some(line_number),
+            Some(actual),
+            Some(expected),
+            None,
+            "obsolete APPEARS TESTED directive, use FLAKY TESTED instead".to_string(),
+        ));
+        LineMark::None
+    } else {
+        line_mark(line)
+    }
+}
+
+#[doc(hidden)]
+fn is_obsolete_line_mark(line: &str) -> bool {
+    line.contains("// APPEARS NOT TESTED")
         || line.contains("/* APPEARS NOT TESTED")
         || line.contains("// BEGIN APPEARS NOT TESTED")
         || line.contains("/* BEGIN APPEARS NOT TESTED")
@@ -407,12 +1142,25 @@ fn extract_line_mark(path: &str, line_number: usize, line: &str) -> LineMark {
         || line.contains("/* END APPEARS NOT TESTED")
         || line.contains("// FILE APPEARS NOT TESTED")
         || line.contains("/* FILE APPEARS NOT TESTED")
-    {
-        eprintln!(
-            "{path}:{line_number}: obsolete APPEARS TESTED directive, use FLAKY TESTED instead"
-        );
-        LineMark::None
-    } else if line.contains("// TESTED") || line.contains("/* TESTED") {
+}
+
+#[doc(hidden)]
+fn obsolete_marker_names(line: &str) -> (&'static str, &'static str) {
+    for (obsolete, replacement) in [
+        ("BEGIN APPEARS NOT TESTED", "BEGIN FLAKY TESTED"),
+        ("END APPEARS NOT TESTED", "END FLAKY TESTED"),
+        ("FILE APPEARS NOT TESTED", "FILE FLAKY TESTED"),
+    ] {
+        if line.contains(obsolete) {
+            return (obsolete, replacement);
+        }
+    }
+    ("APPEARS NOT TESTED", "FLAKY TESTED")
+}
+
+#[doc(hidden)]
+fn line_mark(line: &str) -> LineMark {
+    if line.contains("// TESTED") || line.contains("/* TESTED") {
         LineMark::LineTested
     } else if line.contains("// MAYBE TESTED") || line.contains("/* MAYBE TESTED") {
         LineMark::LineMaybeTested
@@ -443,200 +1191,375 @@ fn extract_line_mark(path: &str, line_number: usize, line: &str) -> LineMark {
     }
 }
 
+// When only checking the lines changed since some git reference, findings about other lines are
+// dropped, and file-level findings are only kept for changed files.
 #[doc(hidden)]
-fn collect_coverage_annotations(
-    path: &Path,
-    coverage_annotations: &mut HashMap<String, HashMap<i32, bool>>,
+fn report_wrong_annotations(
+    config: &Config,
+    source_roots: &[String],
+    coverage_annotations: &BTreeMap<String, HashMap<i32, u64>>,
+    source_annotations: &BTreeMap<String, FileAnnotations>,
+    changed_lines: Option<&BTreeMap<String, BTreeSet<usize>>>,
+    diagnostics: &mut Vec<Diagnostic>,
+    errors: &mut Vec<Error>,
 ) {
-    let file = File::open(path).unwrap_or_else(|_| panic!("can't open {}", path.to_str().unwrap()));
-    let file = BufReader::new(file);
-    let parser = EventReader::new(file);
-    let mut file_name = String::from("unknown");
-    let mut sources: Vec<String> = vec![String::new()];
-    let mut collect_source = false;
-    for event in parser {
-        match event.unwrap() {
-            XmlEvent::StartElement {
-                ref name,
-                ref attributes,
-                ..
-            } => {
-                collect_source = name.local_name == "source";
-                if name.local_name == "class" {
-                    for attribute in attributes {
-                        if attribute.name.local_name == "filename" {
-                            file_name = canonical_file_name(&sources, &attribute.value).unwrap();
-                            coverage_annotations
-                                .entry(file_name.clone())
-                                .or_insert_with(HashMap::new);
-                        }
-                    }
-                }
-                if name.local_name == "line" {
-                    let mut line_number = -1;
-                    let mut hits_count = -1;
-                    for attribute in attributes {
-                        if attribute.name.local_name == "number" {
-                            line_number = attribute.value.parse().unwrap();
-                        } else if attribute.name.local_name == "hits" {
-                            hits_count = attribute.value.parse().unwrap();
-                        }
-                    }
-                    if line_number > 0 {
-                        if hits_count == 0 {
-                            coverage_annotations
-                                .get_mut(&file_name)
-                                .unwrap()
-                                .entry(line_number)
-                                .or_insert(false);
-                        } else {
-                            coverage_annotations
-                                .get_mut(&file_name)
-                                .unwrap()
-                                .insert(line_number, true);
-                        }
-                    }
-                }
-            }
-            XmlEvent::Characters(mut string) => {
-                if collect_source {
-                    if !string.ends_with('/') {
-                        string.push('/');
-                    }
-                    sources.push(string);
-                }
-            }
-            _ => {}
-        };
+    let mut wrong_diagnostics = Vec::new();
+    for (file_name, coverage_line_annotations) in coverage_annotations {
+        if !is_in_source_roots(source_roots, file_name) {
+            continue;
+        }
+        let policy = config.file_policy(file_name);
+        if policy.is_ignored {
+            continue;
+        }
+        if let Some(source_file_annotations) = source_annotations.get(file_name) {
+            report_file_wrong_annotations(
+                policy.flaky_policy,
+                file_name,
+                coverage_line_annotations,
+                source_file_annotations,
+                &mut wrong_diagnostics,
+            );
+        } else if !errors.iter().any(|error| error.file_name() == *file_name) {
+            // Files which could not be read were already reported.
+            errors.push(Error::UnscannedSourceFile {
+                file_name: file_name.clone(),
+            });
+        }
+    }
+    for (file_name, source_file_annotations) in source_annotations {
+        if is_in_source_roots(source_roots, file_name)
+            && coverage_annotations.get(file_name).is_none()
+        {
+            report_uncovered_file_annotations(
+                &config.file_policy(file_name),
+                file_name,
+                source_file_annotations,
+                &mut wrong_diagnostics,
+            );
+        }
     }
+    diagnostics.extend(wrong_diagnostics.into_iter().filter(|diagnostic| {
+        is_in_changed_lines(changed_lines, &diagnostic.file_name, diagnostic.line_number)
+    }));
 }
 
 #[doc(hidden)]
-fn canonical_file_name(sources: &[String], file_name: &str) -> Option<String> {
-    for source in sources {
-        let mut path = PathBuf::from(source);
-        path.push(file_name);
-        if let Ok(canonical) = fs::canonicalize(path) {
-            return Some(canonical.as_path().to_str().unwrap().to_string());
-        }
-    }
-    None
+fn collect_changed_lines(config: &Config) -> Option<BTreeMap<String, BTreeSet<usize>>> {
+    config.changed_since.as_deref().map(|git_ref| {
+        changed_lines(git_ref).unwrap_or_else(|error| {
+            eprintln!("--changed-since: can't diff the working tree against {git_ref}: {error}");
+            std::process::exit(ERROR_EXIT_STATUS);
+        })
+    })
 }
 
+// Everything is changed when not only checking the changed lines.
 #[doc(hidden)]
-fn report_wrong_annotations(
-    flaky_policy: FlakyPolicy,
-    coverage_annotations: &HashMap<String, HashMap<i32, bool>>,
-    source_annotations: &HashMap<String, FileAnnotations>,
-) -> i32 {
-    let mut src = "src".to_string();
-    if let Ok(canonical_src) = fs::canonicalize("src") {
-        src = canonical_src.as_path().to_str().unwrap().to_string();
+fn is_in_changed_lines(
+    changed_lines: Option<&BTreeMap<String, BTreeSet<usize>>>,
+    file_name: &str,
+    line_number: Option<usize>,
+) -> bool {
+    changed_lines.is_none_or(|changed_lines| {
+        changed_lines
+            .get(file_name)
+            .is_some_and(|file_changed_lines| {
+                line_number.is_none_or(|line_number| file_changed_lines.contains(&line_number))
+            })
+    })
+}
+
+// The diagnostics of each package, sorted by file and line.
+#[doc(hidden)]
+fn collect_packages_diagnostics(
+    config: &Config,
+    packages: &[Package],
+    coverage_annotations: &BTreeMap<String, HashMap<i32, u64>>,
+    source_annotations: &BTreeMap<String, FileAnnotations>,
+    source_diagnostics: &[Diagnostic],
+    changed_lines: Option<&BTreeMap<String, BTreeSet<usize>>>,
+    errors: &mut Vec<Error>,
+) -> Vec<Vec<Diagnostic>> {
+    packages
+        .iter()
+        .map(|package| {
+            let mut package_diagnostics: Vec<Diagnostic> = source_diagnostics
+                .iter()
+                .filter(|diagnostic| {
+                    is_in_source_roots(&package.source_roots, &diagnostic.file_name)
+                })
+                .cloned()
+                .collect();
+            report_wrong_annotations(
+                config,
+                &package.source_roots,
+                coverage_annotations,
+                source_annotations,
+                changed_lines,
+                &mut package_diagnostics,
+                errors,
+            );
+            package_diagnostics.sort_by(|left, right| {
+                (&left.file_name, left.line_number).cmp(&(&right.file_name, right.line_number))
+            });
+            package_diagnostics
+        })
+        .collect()
+}
+
+#[doc(hidden)]
+fn wrong_files_count(diagnostics: &[Diagnostic]) -> usize {
+    let mut wrong_file_names: Vec<&str> = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity() == Severity::Error)
+        .map(|diagnostic| diagnostic.file_name.as_str())
+        .collect();
+    wrong_file_names.sort_unstable();
+    wrong_file_names.dedup();
+    wrong_file_names.len()
+}
+
+// In a dry run (`--diff` or `--patch`), the fixes are only written as a unified diff.
+#[doc(hidden)]
+fn fix_packages_annotations(
+    config: &Config,
+    packages: &[Package],
+    coverage_annotations: &BTreeMap<String, HashMap<i32, u64>>,
+    source_annotations: &mut BTreeMap<String, FileAnnotations>,
+    source_diagnostics: &mut Vec<Diagnostic>,
+    errors: &mut Vec<Error>,
+) {
+    let is_dry_run = config.diff || config.patch.is_some();
+    let mut patch = String::new();
+    for package in packages {
+        patch.push_str(&fix_wrong_annotations(
+            config,
+            is_dry_run,
+            &package.source_roots,
+            coverage_annotations,
+            source_annotations,
+            source_diagnostics,
+            errors,
+        ));
     }
-    let mut tests = "tests".to_string();
-    if let Ok(canonical_tests) = fs::canonicalize("tests") {
-        tests = canonical_tests.as_path().to_str().unwrap().to_string();
+    if config.diff {
+        print!("{patch}");
     }
-    let mut exit_status = 0;
-    for (file_name, coverage_line_annotations) in coverage_annotations {
-        if (file_name.starts_with(src.as_str()) || file_name.starts_with(tests.as_str()))
-            && report_file_wrong_annotations(
-                flaky_policy,
-                file_name,
-                coverage_line_annotations,
-                source_annotations.get(file_name).unwrap(),
-            )
-        {
-            exit_status = 1;
-        }
+    if let Some(patch_path) = &config.patch {
+        write_file(patch_path, &patch).unwrap_or_else(|error| {
+            eprintln!("{error}");
+            std::process::exit(ERROR_EXIT_STATUS);
+        });
     }
-    for (file_name, source_file_annotations) in source_annotations {
-        if (file_name.starts_with(src.as_str()) || file_name.starts_with(tests.as_str()))
-            && coverage_annotations.get(file_name).is_none()
-            && report_uncovered_file_annotations(file_name, source_file_annotations)
-        {
-            exit_status = 1;
+}
+
+// In a dry run, the files are left untouched, and the fixes are returned as a unified diff (with
+// paths relative to the current directory) instead.
+#[doc(hidden)]
+fn fix_wrong_annotations(
+    config: &Config,
+    is_dry_run: bool,
+    source_roots: &[String],
+    coverage_annotations: &BTreeMap<String, HashMap<i32, u64>>,
+    source_annotations: &mut BTreeMap<String, FileAnnotations>,
+    source_diagnostics: &mut Vec<Diagnostic>,
+    errors: &mut Vec<Error>,
+) -> String {
+    let mut diffs = String::new();
+    for (file_name, source_file_annotations) in source_annotations.iter_mut() {
+        if !is_in_source_roots(source_roots, file_name) {
+            continue;
+        }
+        let path = Path::new(file_name);
+        let text = match read_file(path) {
+            Ok(text) => text,
+            Err(error) => {
+                errors.push(error);
+                continue;
+            }
+        };
+        let policy = config.file_policy(file_name);
+        if let Some(fixed_text) = fix_file_annotations(
+            &policy,
+            path,
+            &text,
+            coverage_annotations.get(file_name),
+            source_file_annotations,
+            config.fix_region_lines,
+        ) {
+            if is_dry_run {
+                diffs.push_str(&unified_diff(
+                    &relative_file_name(file_name),
+                    &text,
+                    &fixed_text,
+                ));
+            } else {
+                if let Err(error) = write_file(file_name, &fixed_text) {
+                    errors.push(error);
+                    continue;
+                }
+                // The fixed file's line numbers may differ, so it is re-collected from scratch.
+                source_diagnostics.retain(|diagnostic| diagnostic.file_name != *file_name);
+                *source_file_annotations =
+                    collect_text_annotations(&policy, path, &fixed_text, source_diagnostics, None);
+                eprintln!("{file_name}: fixed coverage annotations");
+            }
         }
     }
-    exit_status
+    diffs
+}
+
+// This is computed once, since it is needed for every reported finding and the tool never changes
+// its working directory.
+#[doc(hidden)]
+fn current_dir() -> &'static Path {
+    static CURRENT_DIR: OnceLock<PathBuf> = OnceLock::new();
+    CURRENT_DIR.get_or_init(|| {
+        fs::canonicalize(".").unwrap_or_else(|error| {
+            eprintln!(".: {error}");
+            std::process::exit(ERROR_EXIT_STATUS);
+        })
+    })
+}
+
+#[doc(hidden)]
+fn relative_file_name(file_name: &str) -> String {
+    Path::new(file_name)
+        .strip_prefix(current_dir())
+        .map_or_else(
+            |_| file_name.to_string(),
+            |relative| relative.to_string_lossy().to_string(),
+        )
 }
 
 #[doc(hidden)]
 fn report_file_wrong_annotations(
     flaky_policy: FlakyPolicy,
     file_name: &str,
-    coverage_file_annotations: &HashMap<i32, bool>,
+    coverage_file_annotations: &HashMap<i32, u64>,
     source_file_annotation: &FileAnnotations,
-) -> bool {
+    diagnostics: &mut Vec<Diagnostic>,
+) {
     match *source_file_annotation {
-        FileAnnotations::MaybeTested => false,
+        FileAnnotations::MaybeTested => {}
         FileAnnotations::NotTested => {
-            eprintln!("{file_name}: wrong FILE NOT TESTED coverage annotation");
-            true
+            diagnostics.push(Diagnostic::new(
+                DiagnosticKind::WrongFileNotTested,
+                file_name,
+                None,
+                Some("FILE NOT TESTED"),
+                None,
+                None,
+                "wrong FILE NOT TESTED coverage annotation".to_string(),
+            ));
         }
         FileAnnotations::LineAnnotations(ref source_line_annotations) => {
-            let mut did_report_annotation = false;
             for (mut line_number, source_line_annotation) in
                 source_line_annotations.iter().enumerate()
             {
                 line_number += 1;
                 let coverage_line_annotation = coverage_file_annotations.get(&(line_number as i32));
+                let actual = Some(line_annotation_name(*source_line_annotation));
                 match (
                     flaky_policy,
                     source_line_annotation,
                     coverage_line_annotation,
                 ) {
-                    (_, &LineAnnotation::Tested(_), Some(&false))
-                    | (FlakyPolicy::Tested, &LineAnnotation::FlakyTested(_), Some(&false)) => {
-                        eprintln!("{file_name}:{line_number}: wrong TESTED coverage annotation");
-                        did_report_annotation = true;
-                    }
-
-                    (_, &LineAnnotation::NotTested(_), Some(&true))
-                    | (FlakyPolicy::NotTested, &LineAnnotation::FlakyTested(_), Some(&true)) => {
-                        eprintln!(
-                            "{file_name}:{line_number}: wrong NOT TESTED coverage annotation"
-                        );
-                        did_report_annotation = true;
-                    }
-
-                    (_, &LineAnnotation::Tested(true), None) => {
-                        eprintln!("{file_name}:{line_number}: explicit TESTED coverage annotation for a non-executable line");
-                        did_report_annotation = true;
+                    (_, &LineAnnotation::Tested(_), Some(&0))
+                    | (FlakyPolicy::Tested, &LineAnnotation::FlakyTested(_), Some(&0)) => {
+                        diagnostics.push(Diagnostic::new(
+                            DiagnosticKind::WrongTested,
+                            file_name,
+                            Some(line_number),
+                            actual,
+                            Some("NOT TESTED"),
+                            Some(0),
+                            "wrong TESTED coverage annotation".to_string(),
+                        ));
                     }
 
-                    (_, &LineAnnotation::NotTested(true), None) => {
-                        eprintln!("{file_name}:{line_number}: explicit NOT TESTED coverage annotation for a non-executable line");
-                        did_report_annotation = true;
+                    (_, &LineAnnotation::NotTested(_), Some(&hits))
+                    | (FlakyPolicy::NotTested, &LineAnnotation::FlakyTested(_), Some(&hits))
+                        if hits > 0 =>
+                    {
+                        diagnostics.push(Diagnostic::new(
+                            DiagnosticKind::WrongNotTested,
+                            file_name,
+                            Some(line_number),
+                            actual,
+                            Some("TESTED"),
+                            Some(hits),
+                            "wrong NOT TESTED coverage annotation".to_string(),
+                        ));
                     }
 
-                    (_, &LineAnnotation::MaybeTested(true), None) => {
-                        eprintln!("{file_name}:{line_number}: explicit MAYBE TESTED coverage annotation for a non-executable line");
-                        did_report_annotation = true;
+                    (
+                        _,
+                        &(LineAnnotation::Tested(true)
+                        | LineAnnotation::NotTested(true)
+                        | LineAnnotation::MaybeTested(true)),
+                        None,
+                    ) => {
+                        diagnostics.push(Diagnostic::new(
+                            DiagnosticKind::NonExecutableAnnotation,
+                            file_name,
+                            Some(line_number),
+                            actual,
+                            None,
+                            None,
+                            format!(
+                                "explicit {} coverage annotation for a non-executable line",
+                                line_annotation_name(*source_line_annotation)
+                            ),
+                        ));
                     }
 
                     _ => {}
                 }
             }
-            did_report_annotation
         }
     }
 }
 
+// An uncovered file needs no `FILE NOT TESTED` annotation if its default annotation is `MAYBE
+// TESTED` or `NOT TESTED` and none of its lines is annotated as `TESTED`.
 #[doc(hidden)]
-fn report_uncovered_file_annotations(
-    file_name: &str,
+fn is_uncovered_file_annotated(
+    policy: &FilePolicy,
     source_file_annotations: &FileAnnotations,
 ) -> bool {
-    match *source_file_annotations {
-        FileAnnotations::MaybeTested | FileAnnotations::NotTested => false,
-        FileAnnotations::LineAnnotations(_) => {
-            eprintln!("{file_name}: missing FILE NOT TESTED coverage annotation");
-            true
+    match source_file_annotations {
+        FileAnnotations::MaybeTested | FileAnnotations::NotTested => true,
+        FileAnnotations::LineAnnotations(line_annotations) => {
+            policy.default_annotation != LineAnnotation::Tested(false)
+                && !line_annotations
+                    .iter()
+                    .any(|line_annotation| matches!(line_annotation, LineAnnotation::Tested(_)))
         }
     }
 }
 
+#[doc(hidden)]
+fn report_uncovered_file_annotations(
+    policy: &FilePolicy,
+    file_name: &str,
+    source_file_annotations: &FileAnnotations,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if !is_uncovered_file_annotated(policy, source_file_annotations) {
+        diagnostics.push(Diagnostic::new(
+            DiagnosticKind::MissingFileNotTested,
+            file_name,
+            None,
+            None,
+            Some("FILE NOT TESTED"),
+            None,
+            "missing FILE NOT TESTED coverage annotation".to_string(),
+        ));
+    }
+}
+
 #[doc(hidden)]
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum FlakyPolicy {
@@ -645,32 +1568,29 @@ enum FlakyPolicy {
     Tested,
 }
 
-#[doc(hidden)]
-fn process_args() -> FlakyPolicy {
-    let mut flaky_policy = FlakyPolicy::MaybeTested;
-    let mut args = std::env::args();
-    args.next();
-    let program = args.next().unwrap();
-    for arg in args {
-        match arg.as_str() {
-            "--version" => {
-                println!("cargo-coverage-annotations {VERSION}");
-                std::process::exit(0);
-            }
-            "--flaky=not-tested" => {
-                flaky_policy = FlakyPolicy::NotTested;
-            }
-            "--flaky=maybe-tested" => {
-                flaky_policy = FlakyPolicy::MaybeTested;
-            }
-            "--flaky=tested" => {
-                flaky_policy = FlakyPolicy::Tested;
-            }
-            arg => {
-                eprintln!("{program}: unknown flag \"{arg}\"; valid flags are --version and --flaky=not-tested/maybe-tested/tested");
-                std::process::exit(1);
-            }
+impl std::str::FromStr for FlakyPolicy {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "not-tested" => Ok(Self::NotTested),
+            "maybe-tested" => Ok(Self::MaybeTested),
+            "tested" => Ok(Self::Tested),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FlakyPolicy {
+    #[doc(hidden)]
+    const ALL: [Self; 3] = [Self::NotTested, Self::MaybeTested, Self::Tested];
+
+    #[doc(hidden)]
+    const fn name(self) -> &'static str {
+        match self {
+            Self::NotTested => "not-tested",
+            Self::MaybeTested => "maybe-tested",
+            Self::Tested => "tested",
         }
     }
-    flaky_policy
 }
@@ -0,0 +1,231 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+mod checkstyle;
+mod github;
+mod gitlab;
+mod html;
+mod human;
+mod junit;
+mod markdown;
+mod sarif;
+
+use crate::config::Config;
+use crate::diagnostic::Diagnostic;
+use crate::error::write_file;
+use crate::summary::{summary_json, FileSummary};
+use crate::{FileAnnotations, ERROR_EXIT_STATUS};
+use checkstyle::checkstyle_report;
+use github::github_report;
+use gitlab::gitlab_report;
+use html::html_report;
+use human::human_report;
+use junit::junit_report;
+use markdown::markdown_report;
+use sarif::sarif_report;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::io::IsTerminal;
+use std::str::FromStr;
+
+#[doc(hidden)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OutputFormat {
+    Human,
+    Short,
+    Json,
+    Sarif,
+    Github,
+    Gitlab,
+    Checkstyle,
+    Junit,
+    Markdown,
+    Html,
+}
+
+impl FromStr for OutputFormat {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "human" => Ok(Self::Human),
+            "short" => Ok(Self::Short),
+            "json" => Ok(Self::Json),
+            "sarif" => Ok(Self::Sarif),
+            "github" => Ok(Self::Github),
+            "gitlab" => Ok(Self::Gitlab),
+            "checkstyle" => Ok(Self::Checkstyle),
+            "junit" => Ok(Self::Junit),
+            "markdown" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            _ => Err(()),
+        }
+    }
+}
+
+impl OutputFormat {
+    #[doc(hidden)]
+    pub const ALL: [Self; 10] = [
+        Self::Human,
+        Self::Short,
+        Self::Json,
+        Self::Sarif,
+        Self::Github,
+        Self::Gitlab,
+        Self::Checkstyle,
+        Self::Junit,
+        Self::Markdown,
+        Self::Html,
+    ];
+
+    #[doc(hidden)]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Human => "human",
+            Self::Short => "short",
+            Self::Json => "json",
+            Self::Sarif => "sarif",
+            Self::Github => "github",
+            Self::Gitlab => "gitlab",
+            Self::Checkstyle => "checkstyle",
+            Self::Junit => "junit",
+            Self::Markdown => "markdown",
+            Self::Html => "html",
+        }
+    }
+
+    // The human formats are printed to the standard error as the diagnostics are collected; the
+    // other formats are printed to the standard output once everything has been checked.
+    #[doc(hidden)]
+    pub const fn is_human(self) -> bool {
+        matches!(self, Self::Human | Self::Short)
+    }
+}
+
+#[doc(hidden)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for ColorChoice {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ColorChoice {
+    #[doc(hidden)]
+    pub const ALL: [Self; 3] = [Self::Auto, Self::Always, Self::Never];
+
+    #[doc(hidden)]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Always => "always",
+            Self::Never => "never",
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn is_colored(self) -> bool {
+        match self {
+            Self::Auto => std::io::stderr().is_terminal(),
+            Self::Always => true,
+            Self::Never => false,
+        }
+    }
+}
+
+#[doc(hidden)]
+pub fn write_human_diagnostic(format: OutputFormat, is_colored: bool, diagnostic: &Diagnostic) {
+    if format == OutputFormat::Short {
+        eprintln!("{diagnostic}");
+    } else {
+        eprintln!("{}", human_report(diagnostic, is_colored));
+    }
+}
+
+// The report is written to the standard output unless an output file is given.
+#[doc(hidden)]
+pub fn write_report(
+    config: &Config,
+    coverage_annotations: &BTreeMap<String, HashMap<i32, u64>>,
+    source_annotations: &BTreeMap<String, FileAnnotations>,
+    diagnostics: &[Diagnostic],
+    summaries: Option<&[FileSummary]>,
+) {
+    let file_names: Vec<String> = source_annotations.keys().cloned().collect();
+    let report = match config.format {
+        OutputFormat::Human | OutputFormat::Short => return,
+        OutputFormat::Json => json_report(diagnostics, summaries) + "\n",
+        OutputFormat::Sarif => sarif_report(diagnostics) + "\n",
+        OutputFormat::Github => github_report(diagnostics),
+        OutputFormat::Gitlab => gitlab_report(diagnostics) + "\n",
+        OutputFormat::Checkstyle => checkstyle_report(diagnostics) + "\n",
+        OutputFormat::Junit => {
+            junit_report(&test_file_names(file_names, diagnostics), diagnostics) + "\n"
+        }
+        OutputFormat::Markdown => markdown_report(&file_names, diagnostics, summaries.unwrap()),
+        OutputFormat::Html => html_report(
+            config,
+            coverage_annotations,
+            source_annotations,
+            diagnostics,
+        ),
+    };
+    match config.output.as_deref() {
+        None => print!("{report}"),
+        Some(output) => {
+            write_file(output, &report).unwrap_or_else(|error| {
+                eprintln!("{error}");
+                std::process::exit(ERROR_EXIT_STATUS);
+            });
+        }
+    }
+}
+
+// Tool errors may be about files which are not checked source files (e.g. coverage files), and
+// these are reported as test cases of their own.
+#[doc(hidden)]
+fn test_file_names(mut file_names: Vec<String>, diagnostics: &[Diagnostic]) -> Vec<String> {
+    for diagnostic in diagnostics {
+        if !file_names.contains(&diagnostic.file_name) {
+            file_names.push(diagnostic.file_name.clone());
+        }
+    }
+    file_names
+}
+
+// The summary (if any) is included in the same JSON object, so the output remains a single value.
+#[doc(hidden)]
+fn json_report(diagnostics: &[Diagnostic], summaries: Option<&[FileSummary]>) -> String {
+    let diagnostics: Vec<_> = diagnostics.iter().map(Diagnostic::to_json).collect();
+    let mut report = json!({ "diagnostics": diagnostics });
+    if let Some(summaries) = summaries {
+        report["summary"] = summary_json(summaries);
+    }
+    serde_json::to_string_pretty(&report).unwrap()
+}
@@ -0,0 +1,74 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::diagnostic::Diagnostic;
+use crate::relative_file_name;
+use xml::writer::{EmitterConfig, XmlEvent};
+
+// The diagnostics are grouped by file, in the order each file first appears.
+#[doc(hidden)]
+pub fn checkstyle_report(diagnostics: &[Diagnostic]) -> String {
+    let mut file_names: Vec<&str> = Vec::new();
+    for diagnostic in diagnostics {
+        if !file_names.contains(&diagnostic.file_name.as_str()) {
+            file_names.push(&diagnostic.file_name);
+        }
+    }
+    let mut output = Vec::new();
+    let mut writer = EmitterConfig::new()
+        .perform_indent(true)
+        .create_writer(&mut output);
+    writer
+        .write(XmlEvent::start_element("checkstyle").attr("version", "4.3"))
+        .unwrap();
+    for file_name in file_names {
+        let relative_name = relative_file_name(file_name);
+        writer
+            .write(XmlEvent::start_element("file").attr("name", &relative_name))
+            .unwrap();
+        for diagnostic in diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.file_name == file_name)
+        {
+            let line = diagnostic
+                .line_number
+                .map_or_else(String::new, |line_number| line_number.to_string());
+            let column = diagnostic
+                .comment_columns()
+                .map_or_else(String::new, |(start_column, _)| start_column.to_string());
+            let source = format!("cargo-coverage-annotations.{}", diagnostic.kind.id());
+            let mut error = XmlEvent::start_element("error");
+            if !line.is_empty() {
+                error = error.attr("line", &line);
+            }
+            if !column.is_empty() {
+                error = error.attr("column", &column);
+            }
+            writer
+                .write(
+                    error
+                        .attr("severity", diagnostic.severity().id())
+                        .attr("message", &diagnostic.message)
+                        .attr("source", &source),
+                )
+                .unwrap();
+            writer.write(XmlEvent::end_element()).unwrap();
+        }
+        writer.write(XmlEvent::end_element()).unwrap();
+    }
+    writer.write(XmlEvent::end_element()).unwrap();
+    String::from_utf8(output).unwrap()
+}
@@ -0,0 +1,60 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::diagnostic::Diagnostic;
+use crate::relative_file_name;
+use std::fmt::Write;
+
+// Each diagnostic becomes a workflow command, which GitHub Actions shows as an inline annotation.
+#[doc(hidden)]
+pub fn github_report(diagnostics: &[Diagnostic]) -> String {
+    let mut report = String::new();
+    for diagnostic in diagnostics {
+        write!(
+            report,
+            "::{} file={}",
+            diagnostic.severity().id(),
+            escape_property(&relative_file_name(&diagnostic.file_name))
+        )
+        .unwrap();
+        if let Some(line_number) = diagnostic.line_number {
+            write!(report, ",line={line_number}").unwrap();
+            if let Some((start_column, end_column)) = diagnostic.comment_columns() {
+                write!(report, ",col={start_column},endColumn={end_column}").unwrap();
+            }
+        }
+        writeln!(
+            report,
+            ",title={}::{}",
+            escape_property(diagnostic.kind.id()),
+            escape_data(&diagnostic.message)
+        )
+        .unwrap();
+    }
+    report
+}
+
+#[doc(hidden)]
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[doc(hidden)]
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
@@ -0,0 +1,57 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::diagnostic::{fnv_hash, Diagnostic, Severity};
+use crate::relative_file_name;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+// GitLab merges issues with the same fingerprint, so identical findings on identical lines of the
+// same file are told apart by their order.
+#[doc(hidden)]
+pub fn gitlab_report(diagnostics: &[Diagnostic]) -> String {
+    let mut occurrences: HashMap<String, usize> = HashMap::new();
+    let issues: Vec<Value> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let mut fingerprint = diagnostic.fingerprint();
+            let occurrence = occurrences.entry(fingerprint.clone()).or_default();
+            if *occurrence > 0 {
+                fingerprint = format!(
+                    "{:016x}",
+                    fnv_hash(&[&fingerprint, &occurrence.to_string()])
+                );
+            }
+            *occurrence += 1;
+            json!({
+                "type": "issue",
+                "check_name": diagnostic.kind.id(),
+                "description": diagnostic.message,
+                "categories": ["Bug Risk"],
+                "severity": match diagnostic.severity() {
+                    Severity::Error => "major",
+                    Severity::Warning => "minor",
+                },
+                "fingerprint": fingerprint,
+                "location": {
+                    "path": relative_file_name(&diagnostic.file_name),
+                    "lines": { "begin": diagnostic.line_number.unwrap_or(1) },
+                },
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&issues).unwrap()
+}
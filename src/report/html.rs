@@ -0,0 +1,203 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::config::Config;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::{
+    collect_text_annotations, effective_line_annotation, relative_file_name, FileAnnotations,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+// Everything is inlined in a single page, so the report can be archived as a CI artifact and
+// opened without any network access.
+#[doc(hidden)]
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 1em 2em; }
+table.source { border-collapse: collapse; font-family: monospace; font-size: 0.9em; }
+table.source td { padding: 0 0.5em; vertical-align: top; white-space: pre; }
+td.line, td.hits { color: #888; text-align: right; }
+td.annotation { color: #555; border-right: 1px solid #ccc; }
+td.hit { background: #dfd; }
+td.miss { background: #fdd; }
+tr.error td.code { background: #fcc; }
+tr.warning td.code { background: #ffd; }
+tr.message td { font-family: sans-serif; white-space: normal; padding-bottom: 0.3em; }
+tr.message.error td { color: #b00; }
+tr.message.warning td { color: #860; }
+p.error { color: #b00; }
+p.warning { color: #860; }
+";
+
+// Each file is rendered with gutters for the line number, the coverage hits, and the effective
+// annotation of the line (with the rules which gave it as a tooltip), followed by the source code.
+#[doc(hidden)]
+pub fn html_report(
+    config: &Config,
+    coverage_annotations: &BTreeMap<String, HashMap<i32, u64>>,
+    source_annotations: &BTreeMap<String, FileAnnotations>,
+    diagnostics: &[Diagnostic],
+) -> String {
+    let mut report = String::new();
+    writeln!(
+        report,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Coverage annotations</title>\n<style>{STYLE}</style>\n</head>\n<body>\n\
+         <h1>Coverage annotations</h1>"
+    )
+    .unwrap();
+    report.push_str("<ul>\n");
+    for (file_index, file_name) in source_annotations.keys().enumerate() {
+        let findings_count = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.file_name == *file_name)
+            .count();
+        writeln!(
+            report,
+            "<li><a href=\"#file-{file_index}\">{}</a> ({findings_count} finding{})</li>",
+            escape_html(&relative_file_name(file_name)),
+            if findings_count == 1 { "" } else { "s" }
+        )
+        .unwrap();
+    }
+    report.push_str("</ul>\n");
+    for (file_index, (file_name, source_file_annotations)) in source_annotations.iter().enumerate()
+    {
+        let file_diagnostics: Vec<&Diagnostic> = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.file_name == *file_name)
+            .collect();
+        writeln!(
+            report,
+            "<h2 id=\"file-{file_index}\">{}</h2>",
+            escape_html(&relative_file_name(file_name))
+        )
+        .unwrap();
+        for diagnostic in &file_diagnostics {
+            if diagnostic.line_number.is_none() {
+                writeln!(
+                    report,
+                    "<p class=\"{}\">{}</p>",
+                    diagnostic.severity().id(),
+                    escape_html(&diagnostic.message)
+                )
+                .unwrap();
+            }
+        }
+        write_source_table(
+            &mut report,
+            config,
+            file_name,
+            coverage_annotations.get(file_name),
+            source_file_annotations,
+            &file_diagnostics,
+        );
+    }
+    report.push_str("</body>\n</html>\n");
+    report
+}
+
+#[doc(hidden)]
+fn write_source_table(
+    report: &mut String,
+    config: &Config,
+    file_name: &str,
+    coverage_file_annotations: Option<&HashMap<i32, u64>>,
+    source_file_annotations: &FileAnnotations,
+    file_diagnostics: &[&Diagnostic],
+) {
+    let Ok(text) = fs::read_to_string(file_name) else {
+        report.push_str("<p>The source file could not be read.</p>\n");
+        return;
+    };
+    let mut lines_rules = Vec::new();
+    collect_text_annotations(
+        &config.file_policy(file_name),
+        Path::new(file_name),
+        &text,
+        &mut Vec::new(),
+        Some(&mut lines_rules),
+    );
+    report.push_str("<table class=\"source\">\n");
+    for (line_index, line_text) in text.lines().enumerate() {
+        let line_number = line_index + 1;
+        let annotation =
+            effective_line_annotation(source_file_annotations, line_index).unwrap_or("");
+        let rules = lines_rules[line_index].join("; ");
+        let (hits_class, hits) = match coverage_file_annotations
+            .and_then(|coverage| coverage.get(&(line_number as i32)))
+        {
+            Some(0) => ("hits miss", "0".to_string()),
+            Some(hits) => ("hits hit", hits.to_string()),
+            None => ("hits", String::new()),
+        };
+        let line_diagnostics: Vec<&&Diagnostic> = file_diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.line_number == Some(line_number))
+            .collect();
+        let row_class = if line_diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity() == Severity::Error)
+        {
+            " class=\"error\""
+        } else if line_diagnostics.is_empty() {
+            ""
+        } else {
+            " class=\"warning\""
+        };
+        writeln!(
+            report,
+            "<tr{row_class} id=\"{}:{line_number}\"><td class=\"line\">{line_number}</td>\
+             <td class=\"{hits_class}\">{hits}</td>\
+             <td class=\"annotation\" title=\"{}\">{annotation}</td>\
+             <td class=\"code\">{}</td></tr>",
+            escape_html(&relative_file_name(file_name)),
+            escape_html(&rules),
+            escape_html(line_text)
+        )
+        .unwrap();
+        for diagnostic in line_diagnostics {
+            writeln!(
+                report,
+                "<tr class=\"message {}\"><td colspan=\"3\"></td><td>{}[{}]: {}</td></tr>",
+                diagnostic.severity().id(),
+                diagnostic.severity().id(),
+                diagnostic.kind.id(),
+                escape_html(&diagnostic.message)
+            )
+            .unwrap();
+        }
+    }
+    report.push_str("</table>\n");
+}
+
+#[doc(hidden)]
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
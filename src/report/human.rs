@@ -0,0 +1,158 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::diagnostic::{Diagnostic, DiagnosticKind, Severity};
+use crate::{line_mark, relative_file_name, LineMark};
+use std::fmt::Write;
+
+#[doc(hidden)]
+const ERROR_STYLE: &str = "\x1b[1;31m";
+
+#[doc(hidden)]
+const WARNING_STYLE: &str = "\x1b[1;33m";
+
+#[doc(hidden)]
+const GUTTER_STYLE: &str = "\x1b[1;34m";
+
+#[doc(hidden)]
+const HELP_STYLE: &str = "\x1b[1;36m";
+
+#[doc(hidden)]
+const BOLD_STYLE: &str = "\x1b[1m";
+
+#[doc(hidden)]
+const RESET_STYLE: &str = "\x1b[0m";
+
+// This mimics the way `rustc` renders its diagnostics: a header line, the location, the source
+// line with the annotation comment (or the whole line) underlined, and a suggested fix.
+#[doc(hidden)]
+pub fn human_report(diagnostic: &Diagnostic, is_colored: bool) -> String {
+    let style = |style: &'static str| if is_colored { style } else { "" };
+    let (level_style, level_mark) = match diagnostic.severity() {
+        Severity::Error => (style(ERROR_STYLE), '^'),
+        Severity::Warning => (style(WARNING_STYLE), '-'),
+    };
+    let gutter_style = style(GUTTER_STYLE);
+    let reset = style(RESET_STYLE);
+    let source_line = diagnostic.source_line();
+    let line_number = diagnostic
+        .line_number
+        .map_or_else(String::new, |line_number| line_number.to_string());
+    let gutter = " ".repeat(line_number.len().max(1));
+
+    let mut report = String::new();
+    writeln!(
+        report,
+        "{level_style}{}[{}]{reset}{}: {}{reset}",
+        diagnostic.severity().id(),
+        diagnostic.kind.id(),
+        style(BOLD_STYLE),
+        diagnostic.message
+    )
+    .unwrap();
+    write!(
+        report,
+        "{gutter}{gutter_style}-->{reset} {}",
+        relative_file_name(&diagnostic.file_name)
+    )
+    .unwrap();
+    match (diagnostic.line_number, diagnostic.comment_columns()) {
+        (Some(line_number), Some((start_column, _))) => {
+            writeln!(report, ":{line_number}:{start_column}").unwrap();
+        }
+        (Some(line_number), None) => writeln!(report, ":{line_number}").unwrap(),
+        (None, _) => report.push('\n'),
+    }
+    writeln!(report, "{gutter} {gutter_style}|{reset}").unwrap();
+    if let Some((line, comment_range)) = &source_line {
+        let (start, end) = comment_range.unwrap_or_else(|| {
+            let start = line.len() - line.trim_start().len();
+            (start, line.trim_end().len())
+        });
+        let indent = expand_tabs(&line[..start]).chars().count();
+        let width = expand_tabs(&line[start..end]).chars().count().max(1);
+        writeln!(
+            report,
+            "{gutter_style}{line_number} |{reset} {}",
+            expand_tabs(line)
+        )
+        .unwrap();
+        writeln!(
+            report,
+            "{gutter} {gutter_style}|{reset} {}{level_style}{}{reset}",
+            " ".repeat(indent),
+            level_mark.to_string().repeat(width)
+        )
+        .unwrap();
+        writeln!(report, "{gutter} {gutter_style}|{reset}").unwrap();
+    }
+    let has_comment = source_line
+        .as_ref()
+        .is_some_and(|(_, comment_range)| comment_range.is_some());
+    let is_region_boundary = source_line
+        .as_ref()
+        .is_some_and(|(line, _)| is_region_boundary(line_mark(line)));
+    writeln!(
+        report,
+        "{gutter} {gutter_style}={reset} {}help{reset}: {}",
+        style(HELP_STYLE),
+        help_text(diagnostic, has_comment, is_region_boundary)
+    )
+    .unwrap();
+    report
+}
+
+#[doc(hidden)]
+const fn is_region_boundary(line_mark: LineMark) -> bool {
+    matches!(
+        line_mark,
+        LineMark::BeginMaybeTested
+            | LineMark::BeginNotTested
+            | LineMark::BeginFlakyTested
+            | LineMark::EndMaybeTested
+            | LineMark::EndNotTested
+            | LineMark::EndFlakyTested
+    )
+}
+
+// A line holding a `BEGIN` or `END` marker can't also hold a line annotation, so the region itself
+// needs to change.
+#[doc(hidden)]
+fn help_text(diagnostic: &Diagnostic, has_comment: bool, is_region_boundary: bool) -> String {
+    match (diagnostic.kind, diagnostic.actual, diagnostic.expected) {
+        (DiagnosticKind::MissingFileNotTested, _, _) => {
+            "add a `// FILE NOT TESTED` line at the start of the file".to_string()
+        }
+        (DiagnosticKind::ToolError, _, _) => {
+            "this was skipped, so the coverage annotations were not fully checked".to_string()
+        }
+        (_, _, Some(_)) if is_region_boundary => {
+            "remove the region or move its boundary to an uncovered line, or run with `--fix-regions`"
+                .to_string()
+        }
+        (_, Some(actual), Some(expected)) if has_comment => {
+            format!("replace the `{actual}` annotation with `{expected}`")
+        }
+        (_, _, Some(expected)) => format!("add a `// {expected}` annotation"),
+        (_, Some(actual), None) => format!("remove the `{actual}` annotation"),
+        (_, None, None) => "remove the annotation".to_string(),
+    }
+}
+
+#[doc(hidden)]
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', "    ")
+}
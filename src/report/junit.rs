@@ -0,0 +1,105 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::relative_file_name;
+use xml::writer::{EmitterConfig, XmlEvent};
+
+// Each checked file is a test case, which fails if it has any errors. Each error is a separate
+// failure, and the warnings are reported as the test case output.
+#[doc(hidden)]
+pub fn junit_report(file_names: &[String], diagnostics: &[Diagnostic]) -> String {
+    let tests_count = file_names.len().to_string();
+    let failures_count = file_names
+        .iter()
+        .filter(|file_name| {
+            diagnostics.iter().any(|diagnostic| {
+                diagnostic.file_name == **file_name && diagnostic.severity() == Severity::Error
+            })
+        })
+        .count()
+        .to_string();
+    let mut output = Vec::new();
+    let mut writer = EmitterConfig::new()
+        .perform_indent(true)
+        .create_writer(&mut output);
+    writer
+        .write(
+            XmlEvent::start_element("testsuites")
+                .attr("name", "coverage-annotations")
+                .attr("tests", &tests_count)
+                .attr("failures", &failures_count),
+        )
+        .unwrap();
+    writer
+        .write(
+            XmlEvent::start_element("testsuite")
+                .attr("name", "coverage-annotations")
+                .attr("tests", &tests_count)
+                .attr("failures", &failures_count)
+                .attr("errors", "0")
+                .attr("skipped", "0"),
+        )
+        .unwrap();
+    for file_name in file_names {
+        let relative_name = relative_file_name(file_name);
+        writer
+            .write(
+                XmlEvent::start_element("testcase")
+                    .attr("name", &relative_name)
+                    .attr("classname", "coverage-annotations"),
+            )
+            .unwrap();
+        let mut output_text = String::new();
+        for diagnostic in diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.file_name == *file_name)
+        {
+            let message = diagnostic.line_number.map_or_else(
+                || diagnostic.message.clone(),
+                |line_number| format!("line {line_number}: {}", diagnostic.message),
+            );
+            match diagnostic.severity() {
+                Severity::Error => {
+                    writer
+                        .write(
+                            XmlEvent::start_element("failure")
+                                .attr("type", diagnostic.kind.id())
+                                .attr("message", &message),
+                        )
+                        .unwrap();
+                    writer
+                        .write(XmlEvent::characters(&diagnostic.to_string()))
+                        .unwrap();
+                    writer.write(XmlEvent::end_element()).unwrap();
+                }
+                Severity::Warning => {
+                    output_text.push_str(&diagnostic.to_string());
+                    output_text.push('\n');
+                }
+            }
+        }
+        if !output_text.is_empty() {
+            writer.write(XmlEvent::start_element("system-out")).unwrap();
+            writer.write(XmlEvent::characters(&output_text)).unwrap();
+            writer.write(XmlEvent::end_element()).unwrap();
+        }
+        writer.write(XmlEvent::end_element()).unwrap();
+    }
+    writer.write(XmlEvent::end_element()).unwrap();
+    writer.write(XmlEvent::end_element()).unwrap();
+    String::from_utf8(output).unwrap()
+}
@@ -0,0 +1,157 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::diagnostic::{Diagnostic, DiagnosticKind, Severity};
+use crate::relative_file_name;
+use crate::summary::{total_summary, FileSummary};
+use std::fmt::Write;
+
+// This is meant to be posted as a pull request comment (or appended to `$GITHUB_STEP_SUMMARY`), so
+// it starts with the totals, and the (possibly long) per-file findings are collapsed.
+#[doc(hidden)]
+pub fn markdown_report(
+    file_names: &[String],
+    diagnostics: &[Diagnostic],
+    summaries: &[FileSummary],
+) -> String {
+    let errors_count = severity_count(diagnostics, Severity::Error);
+    let warnings_count = severity_count(diagnostics, Severity::Warning);
+    let mut wrong_file_names: Vec<&str> = Vec::new();
+    for diagnostic in diagnostics {
+        if !wrong_file_names.contains(&diagnostic.file_name.as_str()) {
+            wrong_file_names.push(&diagnostic.file_name);
+        }
+    }
+
+    let mut report = String::from("### Coverage annotations\n\n");
+    if diagnostics.is_empty() {
+        writeln!(
+            report,
+            ":white_check_mark: The coverage annotations of all {} checked files are correct.",
+            file_names.len()
+        )
+        .unwrap();
+    } else {
+        writeln!(
+            report,
+            "{} Found {} and {} in {} of {} checked files.",
+            if errors_count > 0 { ":x:" } else { ":warning:" },
+            plural(errors_count, "error"),
+            plural(warnings_count, "warning"),
+            wrong_file_names.len(),
+            file_names.len()
+        )
+        .unwrap();
+    }
+
+    write_totals_table(&mut report, summaries);
+
+    let uncovered_diagnostics: Vec<&Diagnostic> = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.kind == DiagnosticKind::WrongTested)
+        .collect();
+    if !uncovered_diagnostics.is_empty() {
+        report.push_str("\n#### Newly uncovered lines\n\n");
+        for diagnostic in uncovered_diagnostics {
+            writeln!(
+                report,
+                "- `{}:{}`",
+                relative_file_name(&diagnostic.file_name),
+                diagnostic.line_number.unwrap()
+            )
+            .unwrap();
+        }
+    }
+
+    for file_name in wrong_file_names {
+        let file_diagnostics: Vec<Diagnostic> = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.file_name == file_name)
+            .cloned()
+            .collect();
+        write!(
+            report,
+            "\n<details>\n<summary><code>{}</code>: {}, {}</summary>\n\n",
+            relative_file_name(file_name),
+            plural(severity_count(&file_diagnostics, Severity::Error), "error"),
+            plural(
+                severity_count(&file_diagnostics, Severity::Warning),
+                "warning"
+            )
+        )
+        .unwrap();
+        report.push_str("| Line | Kind | Message |\n| ---: | --- | --- |\n");
+        for diagnostic in &file_diagnostics {
+            writeln!(
+                report,
+                "| {} | `{}` | {} |",
+                diagnostic
+                    .line_number
+                    .map_or_else(String::new, |line_number| line_number.to_string()),
+                diagnostic.kind.id(),
+                diagnostic.message.replace('|', "\\|")
+            )
+            .unwrap();
+        }
+        report.push_str("\n</details>\n");
+    }
+    report
+}
+
+#[doc(hidden)]
+fn write_totals_table(report: &mut String, summaries: &[FileSummary]) {
+    let total = total_summary(summaries);
+    report.push_str(
+        "\n| Executable lines | Covered lines | NOT TESTED | MAYBE TESTED | FLAKY TESTED | Regions |\n",
+    );
+    report.push_str("| ---: | ---: | ---: | ---: | ---: | ---: |\n");
+    // Integer arithmetic avoids the float conversion lints; truncating to a tenth of a percent is
+    // precise enough here.
+    let covered_percent = (total.covered_lines * 1000)
+        .checked_div(total.executable_lines)
+        .map_or_else(String::new, |per_mille| {
+            format!(" ({}.{}%)", per_mille / 10, per_mille % 10)
+        });
+    writeln!(
+        report,
+        "| {} | {}{} | {} | {} | {} | {} |",
+        total.executable_lines,
+        total.covered_lines,
+        covered_percent,
+        total.not_tested_lines,
+        total.maybe_tested_lines,
+        total.flaky_tested_lines,
+        total.regions
+    )
+    .unwrap();
+}
+
+#[doc(hidden)]
+fn severity_count(diagnostics: &[Diagnostic], severity: Severity) -> usize {
+    diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity() == severity)
+        .count()
+}
+
+#[doc(hidden)]
+fn plural(count: usize, name: &str) -> String {
+    if count == 1 {
+        format!("1 {name}")
+    } else {
+        format!("{count} {name}s")
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
+use crate::{current_dir, relative_file_name, VERSION};
+use serde_json::{json, Map, Value};
+use std::fmt::Write;
+
+#[doc(hidden)]
+pub fn sarif_report(diagnostics: &[Diagnostic]) -> String {
+    let rules: Vec<Value> = DiagnosticKind::ALL
+        .iter()
+        .map(|kind| {
+            json!({
+                "id": kind.id(),
+                "shortDescription": { "text": kind.description() },
+                "defaultConfiguration": { "level": kind.severity().id() },
+            })
+        })
+        .collect();
+    let results: Vec<Value> = diagnostics.iter().map(sarif_result).collect();
+    let sarif = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "cargo-coverage-annotations",
+                    "version": VERSION,
+                    "informationUri": "https://github.com/orenbenkiki/cargo-coverage-annotations",
+                    "rules": rules,
+                },
+            },
+            "originalUriBaseIds": {
+                "%SRCROOT%": { "uri": file_uri(&format!("{}/", current_dir().to_string_lossy())) },
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": results,
+        }],
+    });
+    serde_json::to_string_pretty(&sarif).unwrap()
+}
+
+#[doc(hidden)]
+fn sarif_result(diagnostic: &Diagnostic) -> Value {
+    let relative_name = relative_file_name(&diagnostic.file_name);
+    let artifact_location = if relative_name.starts_with('/') {
+        json!({ "uri": file_uri(&relative_name) })
+    } else {
+        json!({ "uri": uri_path(&relative_name), "uriBaseId": "%SRCROOT%" })
+    };
+    let mut physical_location = Map::new();
+    physical_location.insert("artifactLocation".to_string(), artifact_location);
+    if let Some(line_number) = diagnostic.line_number {
+        let mut region = json!({ "startLine": line_number });
+        if let Some((start_column, end_column)) = diagnostic.comment_columns() {
+            region["startColumn"] = json!(start_column);
+            region["endColumn"] = json!(end_column);
+        }
+        physical_location.insert("region".to_string(), region);
+    }
+    let rule_index = DiagnosticKind::ALL
+        .iter()
+        .position(|kind| *kind == diagnostic.kind)
+        .unwrap();
+    json!({
+        "ruleId": diagnostic.kind.id(),
+        "ruleIndex": rule_index,
+        "level": diagnostic.severity().id(),
+        "message": { "text": diagnostic.message },
+        "locations": [{ "physicalLocation": physical_location }],
+    })
+}
+
+#[doc(hidden)]
+fn file_uri(path: &str) -> String {
+    format!("file://{}", uri_path(path))
+}
+
+#[doc(hidden)]
+fn uri_path(path: &str) -> String {
+    let mut uri = String::new();
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            write!(uri, "%{byte:02X}").unwrap();
+        }
+    }
+    uri
+}
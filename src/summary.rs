@@ -0,0 +1,233 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use crate::diagnostic::Diagnostic;
+use crate::{line_mark, relative_file_name, FileAnnotations, LineAnnotation, LineMark};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::fs;
+use std::str::FromStr;
+
+#[doc(hidden)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SummaryFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for SummaryFormat {
+    type Err = ();
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+impl SummaryFormat {
+    #[doc(hidden)]
+    pub const ALL: [Self; 2] = [Self::Text, Self::Json];
+
+    #[doc(hidden)]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::Json => "json",
+        }
+    }
+}
+
+// The annotated line counts only include executable lines, so they can be compared with the number
+// of covered lines. Lines of a FILE NOT/MAYBE TESTED file count as annotated as such.
+#[doc(hidden)]
+#[derive(Debug, Default)]
+pub struct FileSummary {
+    pub file_name: String,
+    pub executable_lines: usize,
+    pub covered_lines: usize,
+    pub not_tested_lines: usize,
+    pub maybe_tested_lines: usize,
+    pub flaky_tested_lines: usize,
+    pub regions: usize,
+    pub findings: usize,
+}
+
+impl FileSummary {
+    #[doc(hidden)]
+    const fn add(&mut self, other: &Self) {
+        self.executable_lines += other.executable_lines;
+        self.covered_lines += other.covered_lines;
+        self.not_tested_lines += other.not_tested_lines;
+        self.maybe_tested_lines += other.maybe_tested_lines;
+        self.flaky_tested_lines += other.flaky_tested_lines;
+        self.regions += other.regions;
+        self.findings += other.findings;
+    }
+
+    #[doc(hidden)]
+    const fn counts(&self) -> [usize; 7] {
+        [
+            self.executable_lines,
+            self.covered_lines,
+            self.not_tested_lines,
+            self.maybe_tested_lines,
+            self.flaky_tested_lines,
+            self.regions,
+            self.findings,
+        ]
+    }
+
+    #[doc(hidden)]
+    fn to_json(&self) -> Value {
+        json!({
+            "executable_lines": self.executable_lines,
+            "covered_lines": self.covered_lines,
+            "not_tested_lines": self.not_tested_lines,
+            "maybe_tested_lines": self.maybe_tested_lines,
+            "flaky_tested_lines": self.flaky_tested_lines,
+            "regions": self.regions,
+            "findings": self.findings,
+        })
+    }
+}
+
+#[doc(hidden)]
+const COLUMN_NAMES: [&str; 7] = [
+    "executable",
+    "covered",
+    "not-tested",
+    "maybe-tested",
+    "flaky-tested",
+    "regions",
+    "findings",
+];
+
+#[doc(hidden)]
+pub fn collect_summary(
+    coverage_annotations: &BTreeMap<String, HashMap<i32, u64>>,
+    source_annotations: &BTreeMap<String, FileAnnotations>,
+    diagnostics: &[Diagnostic],
+) -> Vec<FileSummary> {
+    let mut summaries = Vec::new();
+    for (file_name, source_file_annotations) in source_annotations {
+        let mut summary = FileSummary {
+            file_name: file_name.clone(),
+            ..FileSummary::default()
+        };
+        if let Some(coverage_file_annotations) = coverage_annotations.get(file_name) {
+            for (line_number, hits) in coverage_file_annotations {
+                summary.executable_lines += 1;
+                if *hits > 0 {
+                    summary.covered_lines += 1;
+                }
+                let line_annotation = match source_file_annotations {
+                    FileAnnotations::NotTested => Some(LineAnnotation::NotTested(false)),
+                    FileAnnotations::MaybeTested => Some(LineAnnotation::MaybeTested(false)),
+                    FileAnnotations::LineAnnotations(line_annotations) => {
+                        usize::try_from(*line_number - 1)
+                            .ok()
+                            .and_then(|line_index| line_annotations.get(line_index))
+                            .copied()
+                    }
+                };
+                match line_annotation {
+                    Some(LineAnnotation::NotTested(_)) => summary.not_tested_lines += 1,
+                    Some(LineAnnotation::MaybeTested(_)) => summary.maybe_tested_lines += 1,
+                    Some(LineAnnotation::FlakyTested(_)) => summary.flaky_tested_lines += 1,
+                    Some(LineAnnotation::Tested(_)) | None => {}
+                }
+            }
+        }
+        if let Ok(text) = fs::read_to_string(file_name) {
+            summary.regions = text
+                .lines()
+                .filter(|line| {
+                    matches!(
+                        line_mark(line),
+                        LineMark::BeginNotTested
+                            | LineMark::BeginMaybeTested
+                            | LineMark::BeginFlakyTested
+                    )
+                })
+                .count();
+        }
+        summary.findings = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.file_name == *file_name)
+            .count();
+        summaries.push(summary);
+    }
+    summaries
+}
+
+#[doc(hidden)]
+pub fn total_summary(summaries: &[FileSummary]) -> FileSummary {
+    let mut total = FileSummary::default();
+    for summary in summaries {
+        total.add(summary);
+    }
+    total
+}
+
+#[doc(hidden)]
+pub fn summary_text(summaries: &[FileSummary]) -> String {
+    let total = total_summary(summaries);
+    let total_name = format!("total ({} files)", summaries.len());
+    let mut rows: Vec<(String, [usize; 7])> = summaries
+        .iter()
+        .map(|summary| (relative_file_name(&summary.file_name), summary.counts()))
+        .collect();
+    rows.push((total_name, total.counts()));
+    let name_width = rows
+        .iter()
+        .map(|(name, _)| name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("file".len());
+
+    let mut text = format!("{:name_width$}", "file");
+    for column_name in COLUMN_NAMES {
+        write!(text, " {column_name}").unwrap();
+    }
+    text.push('\n');
+    for (name, counts) in rows {
+        write!(text, "{name:name_width$}").unwrap();
+        for (column_name, count) in COLUMN_NAMES.iter().zip(counts) {
+            write!(text, " {count:>width$}", width = column_name.len()).unwrap();
+        }
+        text.push('\n');
+    }
+    text
+}
+
+#[doc(hidden)]
+pub fn summary_json(summaries: &[FileSummary]) -> Value {
+    let files: Vec<Value> = summaries
+        .iter()
+        .map(|summary| {
+            let mut file = summary.to_json();
+            file["file"] = json!(summary.file_name);
+            file
+        })
+        .collect();
+    let mut total = total_summary(summaries).to_json();
+    total["files"] = json!(summaries.len());
+    json!({ "files": files, "total": total })
+}
@@ -0,0 +1,92 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn run(dir: &Path, args: &[&str]) -> (Option<i32>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-coverage-annotations"))
+        .args(["coverage-annotations", "--format=short"])
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    (
+        output.status.code(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn baseline_entries_of_unchecked_packages_are_not_fixed() {
+    let fixture_dir = fs::canonicalize("tests/fixtures/workspace").unwrap();
+    let baseline_path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("workspace-baseline.json");
+    let baseline = baseline_path.to_str().unwrap();
+    let (status, _) = run(&fixture_dir, &["--workspace", "--write-baseline", baseline]);
+    assert_eq!(status, Some(0));
+    let (status, stderr) = run(&fixture_dir, &["-p", "b", "--baseline", baseline]);
+    assert_eq!(status, Some(0));
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn baseline_entries_outside_the_changed_lines_are_not_fixed() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("changed-baseline");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("src/lib.rs"),
+        "pub fn a() {\n    x(); // NOT TESTED\n}\n",
+    )
+    .unwrap();
+    fs::write(dir.join("src/other.rs"), "pub fn b() {}\n").unwrap();
+    fs::write(
+        dir.join("lcov.info"),
+        "SF:src/lib.rs\nDA:1,1\nDA:2,1\nend_of_record\n",
+    )
+    .unwrap();
+    git(&dir, &["init", "--quiet"]);
+    git(&dir, &["add", "."]);
+    git(&dir, &["commit", "--quiet", "-m", "initial"]);
+    let args = ["--coverage", "lcov.info", "--source", "src"];
+    let (status, _) = run(
+        &dir,
+        &[&args[..], &["--write-baseline", "baseline.json"]].concat(),
+    );
+    assert_eq!(status, Some(0));
+    fs::write(dir.join("src/other.rs"), "pub fn b() {}\npub fn c() {}\n").unwrap();
+    let (status, stderr) = run(
+        &dir,
+        &[
+            &args[..],
+            &["--changed-since", "HEAD", "--baseline", "baseline.json"],
+        ]
+        .concat(),
+    );
+    assert_eq!(status, Some(0));
+    assert_eq!(stderr, "");
+}
@@ -0,0 +1,47 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use std::fs;
+use std::process::Command;
+
+// The fixture's `coverage.json` is not an `llvm-cov` export.
+fn run_llvm_cov_other(args: &[&str]) -> (Option<i32>, String) {
+    let fixture_dir = fs::canonicalize("tests/fixtures/llvm-cov-other").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-coverage-annotations"))
+        .args(["coverage-annotations", "--source", "src", "--format=short"])
+        .args(args)
+        .current_dir(&fixture_dir)
+        .output()
+        .unwrap();
+    (
+        output.status.code(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn crawled_json_files_which_are_not_llvm_cov_exports_are_ignored() {
+    let (status, stderr) = run_llvm_cov_other(&[]);
+    assert_eq!(status, Some(0));
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn explicit_json_files_which_are_not_llvm_cov_exports_are_errors() {
+    let (status, stderr) = run_llvm_cov_other(&["--coverage", "coverage.json"]);
+    assert_eq!(status, Some(2));
+    assert_eq!(stderr, "coverage.json: not an llvm-cov JSON export\n");
+}
@@ -0,0 +1,66 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+// Each fixture holds the expected `--diff` output in `expected.diff`.
+fn run_fix_fixture(name: &str, args: &[&str]) {
+    let fixture_dir = fs::canonicalize(Path::new("tests/fixtures").join(name)).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-coverage-annotations"))
+        .args([
+            "coverage-annotations",
+            "--coverage",
+            "lcov.info",
+            "--source",
+            "src",
+            "fix",
+            "--diff",
+        ])
+        .args(args)
+        .current_dir(&fixture_dir)
+        .output()
+        .unwrap();
+    let expected = fs::read_to_string(fixture_dir.join("expected.diff")).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
+
+#[test]
+fn fix_adds_and_removes_line_markers() {
+    run_fix_fixture("fix-markers", &[]);
+}
+
+#[test]
+fn fix_uses_block_markers_in_block_style_files() {
+    run_fix_fixture("fix-block", &[]);
+}
+
+#[test]
+fn fix_adds_and_removes_file_markers() {
+    run_fix_fixture("fix-file", &[]);
+}
+
+#[test]
+fn fix_coalesces_and_shrinks_regions() {
+    run_fix_fixture("fix-regions", &["--fix-regions=2"]);
+}
+
+#[test]
+fn fix_removes_regions_with_covered_boundaries() {
+    run_fix_fixture("fix-region-boundary", &[]);
+}
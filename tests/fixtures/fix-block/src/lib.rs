@@ -0,0 +1,8 @@
+pub fn covered() {
+    a(); /* NOT TESTED */
+}
+
+pub fn uncovered() {
+    b(); /* MAYBE TESTED */
+    c();
+}
@@ -0,0 +1,5 @@
+// FILE NOT TESTED
+pub fn covered() {
+    a();
+    b();
+}
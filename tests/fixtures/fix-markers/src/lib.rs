@@ -0,0 +1,13 @@
+pub fn covered() {
+    a(); // NOT TESTED: only reached on windows, see #12
+    b(); // TESTED
+}
+
+pub fn uncovered() {
+    c();
+    d(); // TESTED
+}
+
+pub fn unknown() {
+    e(); // NOT TESTED
+}
@@ -0,0 +1,3 @@
+pub fn last() {
+    f();
+}
\ No newline at end of file
@@ -0,0 +1 @@
+pub fn one_line() { f() }
\ No newline at end of file
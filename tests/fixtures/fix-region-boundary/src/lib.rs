@@ -0,0 +1,10 @@
+pub fn region() { // BEGIN NOT TESTED
+    a();
+    b(); // TESTED
+    c();
+} // END NOT TESTED
+
+pub fn kept() { // BEGIN NOT TESTED
+    d();
+    e();
+} // END NOT TESTED
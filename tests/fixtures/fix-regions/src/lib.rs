@@ -0,0 +1,19 @@
+pub fn run() {
+    a();
+    b();
+
+    c();
+    d();
+}
+
+pub fn region() { // BEGIN NOT TESTED
+    e();
+    f();
+    g();
+    h();
+} // END NOT TESTED
+
+pub fn single() {
+    i();
+    j();
+}
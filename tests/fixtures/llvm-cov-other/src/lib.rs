@@ -0,0 +1,2 @@
+// FILE NOT TESTED
+pub fn a() {}
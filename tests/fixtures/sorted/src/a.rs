@@ -0,0 +1,4 @@
+// BEGIN NOT TESTED
+// BEGIN NOT TESTED
+pub fn a() {}
+// END NOT TESTED
@@ -0,0 +1,8 @@
+pub fn f() {
+    g(); // NOT TESTED
+    h(); // TESTED
+}
+// BEGIN NOT TESTED
+pub fn g() {}
+// END NOT TESTED
+pub fn h() {}
@@ -0,0 +1,3 @@
+mod platform;
+
+pub fn a() {}
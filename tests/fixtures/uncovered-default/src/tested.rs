@@ -0,0 +1,3 @@
+pub fn c() {
+    y(); // TESTED
+}
@@ -0,0 +1,3 @@
+pub fn a() {
+    x(); // NOT TESTED
+}
@@ -0,0 +1,57 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use std::fs;
+use std::process::Command;
+
+// The `BEGIN` line of the first region is covered, and so is the first line inside the second.
+const EXPECTED_OUTPUT: &str = "\
+error[wrong-not-tested]: wrong NOT TESTED coverage annotation
+ --> src/lib.rs:1
+  |
+1 | pub fn region() { // BEGIN NOT TESTED
+  | ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+  |
+  = help: remove the region or move its boundary to an uncovered line, or run with `--fix-regions`
+
+error[wrong-not-tested]: wrong NOT TESTED coverage annotation
+ --> src/lib.rs:8
+  |
+8 |     d();
+  |     ^^^^
+  |
+  = help: add a `// TESTED` annotation
+
+";
+
+#[test]
+fn help_for_region_boundaries_does_not_add_annotations() {
+    let fixture_dir = fs::canonicalize("tests/fixtures/fix-region-boundary").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-coverage-annotations"))
+        .args([
+            "coverage-annotations",
+            "--coverage",
+            "lcov.info",
+            "--source",
+            "src",
+            "--format=human",
+        ])
+        .current_dir(&fixture_dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), EXPECTED_OUTPUT);
+}
@@ -0,0 +1,56 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const EXPECTED_OUTPUT: &str = "\
+src/a.rs: missing FILE NOT TESTED coverage annotation
+src/a.rs:2: ignored nested BEGIN NOT TESTED coverage annotation
+src/lib.rs:2: wrong NOT TESTED coverage annotation
+src/lib.rs:3: redundant TESTED coverage annotation
+src/lib.rs:8: wrong TESTED coverage annotation
+src/z/b.rs:1: wrong TESTED coverage annotation
+";
+
+fn run_fixture(name: &str) -> (Option<i32>, String) {
+    let fixture_dir = fs::canonicalize(Path::new("tests/fixtures").join(name)).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-coverage-annotations"))
+        .args([
+            "coverage-annotations",
+            "--coverage",
+            "lcov.info",
+            "--source",
+            "src",
+            "--format=short",
+        ])
+        .current_dir(&fixture_dir)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let prefix = format!("{}/", fixture_dir.to_str().unwrap());
+    (output.status.code(), stderr.replace(&prefix, ""))
+}
+
+#[test]
+fn findings_are_sorted_by_file_then_line() {
+    for _ in 0..5 {
+        let (status, stderr) = run_fixture("sorted");
+        assert_eq!(status, Some(1));
+        assert_eq!(stderr, EXPECTED_OUTPUT);
+    }
+}
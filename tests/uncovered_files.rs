@@ -0,0 +1,61 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use std::fs;
+use std::process::Command;
+
+// Only `src/lib.rs` is covered, and the other files default to `MAYBE TESTED`.
+fn run_uncovered_default(args: &[&str]) -> (Option<i32>, String, String) {
+    let fixture_dir = fs::canonicalize("tests/fixtures/uncovered-default").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-coverage-annotations"))
+        .args([
+            "coverage-annotations",
+            "--coverage",
+            "lcov.info",
+            "--source",
+            "src",
+        ])
+        .args(args)
+        .arg("--format=short")
+        .current_dir(&fixture_dir)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let prefix = format!("{}/", fixture_dir.to_str().unwrap());
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).unwrap(),
+        stderr.replace(&prefix, ""),
+    )
+}
+
+#[test]
+fn uncovered_files_with_untested_default_need_no_file_annotation() {
+    let (status, _, stderr) = run_uncovered_default(&[]);
+    assert_eq!(status, Some(1));
+    assert_eq!(
+        stderr,
+        "src/tested.rs: missing FILE NOT TESTED coverage annotation\n"
+    );
+}
+
+#[test]
+fn fix_only_annotates_uncovered_files_needing_it() {
+    let (status, stdout, _) = run_uncovered_default(&["fix", "--diff"]);
+    assert_eq!(status, Some(1));
+    assert!(stdout.contains("src/tested.rs"));
+    assert!(!stdout.contains("src/platform.rs"));
+}
@@ -0,0 +1,76 @@
+// Copyright (C) 2017-2021 Oren Ben-Kiki <oren@ben-kiki.org>
+//
+// This file is part of cargo-coverage-annotations.
+//
+// cargo-coverage-annotations is free software: you can redistribute it and/or
+// modify it under the terms of the GNU General Public License, version 3, as
+// published by the Free Software Foundation.
+//
+// cargo-coverage-annotations is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU General
+// Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// cargo-coverage-annotations. If not, see <http://www.gnu.org/licenses/>.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+// Runs in a directory of the `workspace` fixture, whose configuration is in the workspace manifest.
+fn run_workspace(dir: &str, args: &[&str]) -> (Option<i32>, String) {
+    let fixture_dir = fs::canonicalize("tests/fixtures/workspace").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-coverage-annotations"))
+        .args(["coverage-annotations", "--format=short"])
+        .args(args)
+        .current_dir(Path::new(&fixture_dir).join(dir))
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let prefix = format!("{}/", fixture_dir.to_str().unwrap());
+    (output.status.code(), stderr.replace(&prefix, ""))
+}
+
+#[test]
+fn workspace_config_paths_are_relative_to_the_workspace_root() {
+    let (status, stderr) = run_workspace("crates/b", &[]);
+    assert_eq!(status, Some(1));
+    assert_eq!(
+        stderr,
+        "\
+crates/b/src/lib.rs:2: redundant TESTED coverage annotation
+crates/b/src/lib.rs:2: wrong TESTED coverage annotation
+"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn invalid_cargo_metadata_is_a_tool_error() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("invalid-metadata");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("Cargo.toml"), "[package]\nname = \"x\"\n").unwrap();
+    let cargo = dir.join("cargo");
+    fs::write(
+        &cargo,
+        "#!/bin/sh\necho '{\"packages\": [{\"name\": \"x\"}], \"workspace_members\": []}'\n",
+    )
+    .unwrap();
+    fs::set_permissions(&cargo, fs::Permissions::from_mode(0o755)).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-coverage-annotations"))
+        .args(["coverage-annotations", "--format=short"])
+        .env("CARGO", &cargo)
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let prefix = format!("{}/", fs::canonicalize(&dir).unwrap().to_str().unwrap());
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        stderr.replace(&prefix, ""),
+        "Cargo.toml: invalid cargo metadata: package without a manifest_path\n"
+    );
+}